@@ -1,20 +1,766 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
 use std::io::{self, BufRead};
+use std::process;
+
+const USAGE: &str = "usage: dec01 [--target N] [--count K]... [--all|--first] \
+                     [--subset|--minimal|--solutions] \
+                     [--skip-blank] [--skip-comments] < expenses";
 
 fn main() {
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
     let stdin = io::stdin();
-    let mut expenses: Vec<u32> = vec![];
+    let expenses = match parse_expenses(stdin.lock(), &options.input) {
+        Ok(expenses) => expenses,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
+
+    match options.subset {
+        Some(query) => print_subsets(&expenses, &options, query),
+        None => print_k_sums(&expenses, &options),
+    }
+}
+
+fn print_k_sums(expenses: &[i64], options: &Options) {
+    for &count in &options.counts {
+        let mut combinations = k_sum(expenses, count, options.target);
+        if options.first_only {
+            combinations.truncate(1);
+        }
+
+        for combination in combinations {
+            let entries: Vec<String> = combination.iter()
+                .map(|&i| expenses[i].to_string())
+                .collect();
+            let product = match checked_product(expenses, &combination) {
+                Ok(product) => product,
+                Err(err) => {
+                    eprintln!("{}: {}", entries.join(" * "), err);
+                    process::exit(1);
+                },
+            };
+
+            // count, target, entries, product - one tab separated row per match
+            println!("{}\t{}\t{}\t{}", count, options.target, entries.join(","), product);
+        }
+    }
+}
 
-    for line in stdin.lock().lines() {
-        expenses.push(line.unwrap().parse::<u32>().unwrap());
+fn print_subsets(expenses: &[i64], options: &Options, query: SubsetQuery) {
+    let table = match SubsetSum::new(expenses, options.target) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        },
+    };
+
+    let subsets = match query {
+        SubsetQuery::Solutions => {
+            // target, number of subsets adding up to it
+            match table.count() {
+                Some(count) => println!("{}\t{}", options.target, count),
+                None => println!("{}\t>{}", options.target, u128::MAX),
+            }
+            return;
+        },
+        SubsetQuery::Minimal => table.minimal().into_iter().collect(),
+        SubsetQuery::Witnesses if options.first_only => table.witness().into_iter().collect(),
+        SubsetQuery::Witnesses => table.all(),
+    };
+
+    for subset in subsets {
+        let entries: Vec<String> = subset.iter()
+            .map(|&i| expenses[i].to_string())
+            .collect();
+
+        // size, target, entries - one tab separated row per subset
+        println!("{}\t{}\t{}", subset.len(), options.target, entries.join(","));
     }
+}
+
+/// Command line options. Without any `--count` both puzzle parts (pairs and
+/// triples) are answered.
+#[derive(Debug, PartialEq)]
+struct Options {
+    target: i64,
+    counts: Vec<usize>,
+    first_only: bool,
+    subset: Option<SubsetQuery>,
+    input: InputFormat,
+}
+
+/// What to report when searching subsets of any size instead of a fixed
+/// number of entries.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum SubsetQuery {
+    Witnesses,
+    Minimal,
+    Solutions,
+}
 
-    for e1 in &expenses {
-        for e2 in &expenses {
-            for e3 in &expenses {
-                if e1 + e2 + e3 == 2020 {
-                    eprintln!("{} + {} + {} = {}", e1, e2, e3, e1 * e2 * e3);
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            target: 2020,
+            counts: vec![2, 3],
+            first_only: false,
+            subset: None,
+            input: InputFormat::default(),
+        }
+    }
+}
+
+impl Options {
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, &'static str> {
+        let mut options = Options::default();
+        let mut counts = vec![];
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" | "-t" => {
+                    options.target = args.next()
+                        .ok_or("Missing value for --target")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --target")?;
+                },
+                "--count" | "-k" => {
+                    counts.push(args.next()
+                        .ok_or("Missing value for --count")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --count")?);
+                },
+                "--all" => options.first_only = false,
+                "--first" => options.first_only = true,
+                "--subset" => options.subset = Some(SubsetQuery::Witnesses),
+                "--minimal" => options.subset = Some(SubsetQuery::Minimal),
+                "--solutions" => options.subset = Some(SubsetQuery::Solutions),
+                "--skip-blank" => options.input.skip_blank = true,
+                "--skip-comments" => options.input.skip_comments = true,
+                "--help" | "-h" => return Err("Help requested"),
+                _ => return Err("Unknown argument"),
+            }
+        }
+
+        if !counts.is_empty() {
+            options.counts = counts;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Which non-amount lines are tolerated in the input. Comments are lines
+/// starting with `#`.
+#[derive(Debug, Default, PartialEq)]
+struct InputFormat {
+    skip_blank: bool,
+    skip_comments: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    text: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {} {:?}", self.line, self.reason, self.text)
+    }
+}
+
+/// Reads one amount per line. Surrounding whitespace (including the `\r` of a
+/// CRLF line ending) is ignored and amounts may be signed.
+fn parse_expenses<T: BufRead>(reader: T, format: &InputFormat) -> Result<Vec<i64>, ParseError> {
+    let mut expenses = vec![];
+
+    for (i, line) in reader.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.map_err(|_| ParseError {
+            line: line_number,
+            text: String::new(),
+            reason: "Unable to read line",
+        })?;
+        let value = line.trim();
+
+        if value.is_empty() {
+            if format.skip_blank {
+                continue;
+            }
+            return Err(ParseError { line: line_number, text: line, reason: "Blank line" });
+        }
+
+        if value.starts_with('#') {
+            if format.skip_comments {
+                continue;
+            }
+            return Err(ParseError { line: line_number, text: line, reason: "Unexpected comment" });
+        }
+
+        match value.parse::<i64>() {
+            Ok(amount) => expenses.push(amount),
+            Err(_) => {
+                return Err(ParseError { line: line_number, text: line, reason: "Invalid amount" });
+            },
+        }
+    }
+
+    Ok(expenses)
+}
+
+/// Finds every combination of `k` distinct entries in `values` that adds up
+/// to `target`.
+///
+/// Each combination is returned as a sorted list of indices into `values`, so
+/// an entry is never used twice and no permutation is reported more than
+/// once. Runs in O(n^(k-1)) for `k >= 2` (plus the size of the output).
+fn k_sum(values: &[i64], k: usize, target: i64) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by_key(|&i| values[i]);
+    let sorted: Vec<i64> = order.iter().map(|&i| values[i]).collect();
+
+    let mut combinations = vec![];
+    k_sum_sorted(&sorted, 0, k, i128::from(target), &mut vec![], &mut combinations);
+
+    let mut result: Vec<Vec<usize>> = combinations.into_iter()
+        .map(|combination| {
+            let mut indices: Vec<usize> = combination.into_iter()
+                .map(|i| order[i])
+                .collect();
+            indices.sort_unstable();
+            indices
+        })
+        .collect();
+    result.sort();
+    result
+}
+
+fn k_sum_sorted(
+    sorted: &[i64],
+    start: usize,
+    k: usize,
+    target: i128,
+    prefix: &mut Vec<usize>,
+    result: &mut Vec<Vec<usize>>,
+) {
+    match k {
+        0 => {
+            if target == 0 {
+                result.push(prefix.clone());
+            }
+        },
+        1 => {
+            for (i, &value) in sorted.iter().enumerate().skip(start) {
+                if i128::from(value) == target {
+                    result.push(with_indices(prefix, &[i]));
+                }
+            }
+        },
+        2 => {
+            if sorted.len() < start + 2 {
+                return;
+            }
+
+            let mut lo = start;
+            let mut hi = sorted.len() - 1;
+
+            while lo < hi {
+                let sum = i128::from(sorted[lo]) + i128::from(sorted[hi]);
+                if sum < target {
+                    lo += 1;
+                } else if sum > target {
+                    hi -= 1;
+                } else if sorted[lo] == sorted[hi] {
+                    // Everything between the pointers is equal, so every pair
+                    // within the run is a match.
+                    for i in lo..=hi {
+                        for j in i + 1..=hi {
+                            result.push(with_indices(prefix, &[i, j]));
+                        }
+                    }
+                    break;
+                } else {
+                    let lo_end = lo + sorted[lo..].iter()
+                        .take_while(|&&v| v == sorted[lo])
+                        .count();
+                    let hi_start = hi + 1 - sorted[..=hi].iter()
+                        .rev()
+                        .take_while(|&&v| v == sorted[hi])
+                        .count();
+
+                    for i in lo..lo_end {
+                        for j in hi_start..=hi {
+                            result.push(with_indices(prefix, &[i, j]));
+                        }
+                    }
+
+                    lo = lo_end;
+                    hi = hi_start - 1;
+                }
+            }
+        },
+        _ => {
+            for i in start..sorted.len() {
+                let value = i128::from(sorted[i]);
+                // The remaining entries are all at least `value`, so no
+                // combination starting here can be small enough.
+                if value.saturating_mul(k as i128) > target {
+                    break;
+                }
+
+                prefix.push(i);
+                k_sum_sorted(sorted, i + 1, k - 1, target - value, prefix, result);
+                prefix.pop();
+            }
+        },
+    }
+}
+
+/// Dynamic programming table answering which subsets, of any size, of a list
+/// of non-negative amounts add up to a target.
+///
+/// Building the table takes O(n * target) time and O(target) memory plus the
+/// improvement history described below.
+struct SubsetSum<'a> {
+    values: &'a [i64],
+    target: usize,
+    /// For every sum, each time a prefix of the values reached it with fewer
+    /// entries than before: `(prefix length, entries used)`. The first record
+    /// tells from which prefix on the sum is reachable at all and the last
+    /// record before a prefix length gives the minimal cardinality there.
+    improvements: Vec<Vec<(u32, u32)>>,
+}
+
+impl<'a> SubsetSum<'a> {
+    fn new(values: &'a [i64], target: i64) -> Result<Self, &'static str> {
+        if values.iter().any(|&v| v < 0) {
+            return Err("Subset sums require non-negative amounts");
+        }
+        let target = usize::try_from(target).map_err(|_| "Subset sums require a non-negative target")?;
+
+        if values.len() >= u32::MAX as usize {
+            return Err("Too many amounts for subset sums");
+        }
+
+        let mut best = vec![u32::MAX; target + 1];
+        let mut improvements = vec![vec![]; target + 1];
+        best[0] = 0;
+        improvements[0].push((0, 0));
+
+        for (i, &value) in values.iter().enumerate() {
+            let value = match usize::try_from(value) {
+                Ok(value) if value <= target => value,
+                _ => continue,
+            };
+
+            // Descending, so that every sum is extended by the state from
+            // before this value and no value is used twice.
+            for sum in (value..=target).rev() {
+                let from = sum - value;
+                if best[from] != u32::MAX && best[from] + 1 < best[sum] {
+                    best[sum] = best[from] + 1;
+                    improvements[sum].push((i as u32 + 1, best[sum]));
                 }
             }
         }
+
+        Ok(SubsetSum {
+            values,
+            target,
+            improvements,
+        })
+    }
+
+    /// Number of subsets (by index) adding up to the target, or `None` if
+    /// there are more than `u128::MAX`. Runs its own O(n * target) pass.
+    fn count(&self) -> Option<u128> {
+        // `None` for sums with too many subsets to count
+        let mut counts = vec![Some(0u128); self.target + 1];
+        counts[0] = Some(1);
+
+        for i in 0..self.values.len() {
+            let value = self.value(i);
+            if value > self.target {
+                continue;
+            }
+
+            for sum in (value..=self.target).rev() {
+                counts[sum] = counts[sum].zip(counts[sum - value]).and_then(|(a, b)| a.checked_add(b));
+            }
+        }
+
+        counts[self.target]
+    }
+
+    /// Whether `sum` can be formed from the first `prefix` values.
+    fn reachable(&self, sum: usize, prefix: usize) -> bool {
+        self.improvements[sum].first().is_some_and(|&(from, _)| from as usize <= prefix)
+    }
+
+    fn value(&self, i: usize) -> usize {
+        self.values[i] as usize
+    }
+
+    /// Some subset adding up to the target.
+    fn witness(&self) -> Option<Vec<usize>> {
+        if !self.reachable(self.target, self.values.len()) {
+            return None;
+        }
+
+        let mut result = vec![];
+        self.walk(self.target, self.values.len(), &mut vec![], &mut |subset| {
+            result.push(subset.to_vec());
+            false
+        });
+        result.pop()
+    }
+
+    /// Every subset adding up to the target, each as sorted indices.
+    fn all(&self) -> Vec<Vec<usize>> {
+        if !self.reachable(self.target, self.values.len()) {
+            return vec![];
+        }
+
+        let mut result = vec![];
+        self.walk(self.target, self.values.len(), &mut vec![], &mut |subset| {
+            result.push(subset.to_vec());
+            true
+        });
+        result.sort();
+        result
+    }
+
+    /// Depth-first search over the subsets of the first `prefix` values that
+    /// add up to `sum`. Only branches that lead to a solution are entered.
+    /// Returns false once `emit` asks to stop.
+    fn walk<F: FnMut(&[usize]) -> bool>(
+        &self,
+        sum: usize,
+        prefix: usize,
+        subset: &mut Vec<usize>,
+        emit: &mut F,
+    ) -> bool {
+        if prefix == 0 {
+            let mut sorted = subset.clone();
+            sorted.reverse();
+            return emit(&sorted);
+        }
+
+        let i = prefix - 1;
+        let value = self.value(i);
+        if value <= sum && self.reachable(sum - value, i) {
+            subset.push(i);
+            let go_on = self.walk(sum - value, i, subset, emit);
+            subset.pop();
+            if !go_on {
+                return false;
+            }
+        }
+
+        if self.reachable(sum, i) {
+            return self.walk(sum, i, subset, emit);
+        }
+
+        true
+    }
+
+    /// A subset with as few entries as possible adding up to the target.
+    fn minimal(&self) -> Option<Vec<usize>> {
+        let mut subset = vec![];
+        let mut sum = self.target;
+        let mut prefix = self.values.len();
+
+        if !self.reachable(sum, prefix) {
+            return None;
+        }
+
+        while sum > 0 {
+            // The last improvement within the prefix names the value that
+            // made the minimal count; its remainder was reached before it.
+            let &(from, _) = self.improvements[sum].iter()
+                .rev()
+                .find(|&&(from, _)| from as usize <= prefix)?;
+            let i = from as usize - 1;
+            subset.push(i);
+            sum -= self.value(i);
+            prefix = i;
+        }
+
+        subset.reverse();
+        Some(subset)
+    }
+}
+
+/// Multiplies the entries of a combination. Amounts are at most 64 bits wide
+/// so pairs always fit, while longer combinations may overflow even `i128`.
+fn checked_product(values: &[i64], combination: &[usize]) -> Result<i128, &'static str> {
+    combination.iter()
+        .try_fold(1i128, |product, &i| product.checked_mul(i128::from(values[i])))
+        .ok_or("Product overflows 128 bits")
+}
+
+fn with_indices(prefix: &[usize], indices: &[usize]) -> Vec<usize> {
+    let mut combination = prefix.to_vec();
+    combination.extend_from_slice(indices);
+    combination
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_INPUT: &[i64] = &[1721, 979, 366, 299, 675, 1456];
+
+    fn parse_args(args: &[&str]) -> Result<Options, &'static str> {
+        Options::from_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parsing_arguments() {
+        assert_eq!(parse_args(&[]), Ok(Options::default()));
+        assert_eq!(
+            parse_args(&["--target", "100", "--count", "4", "--count", "2", "--first"]),
+            Ok(Options {
+                target: 100,
+                counts: vec![4, 2],
+                first_only: true,
+                subset: None,
+                input: InputFormat::default(),
+            }),
+        );
+        assert_eq!(parse_args(&["--first", "--all"]).map(|o| o.first_only), Ok(false));
+        assert_eq!(parse_args(&["--target"]), Err("Missing value for --target"));
+        assert_eq!(parse_args(&["--count", "-1"]), Err("Invalid value for --count"));
+        assert_eq!(parse_args(&["--sum"]), Err("Unknown argument"));
+        assert_eq!(parse_args(&["--subset", "--minimal"]).map(|o| o.subset), Ok(Some(SubsetQuery::Minimal)));
+        assert_eq!(
+            parse_args(&["--skip-blank", "--skip-comments"]).map(|o| o.input),
+            Ok(InputFormat { skip_blank: true, skip_comments: true }),
+        );
+    }
+
+    fn parse(input: &str, format: &InputFormat) -> Result<Vec<i64>, ParseError> {
+        parse_expenses(io::Cursor::new(input), format)
+    }
+
+    #[test]
+    fn test_parsing_input() {
+        let strict = InputFormat::default();
+
+        assert_eq!(parse("1721\r\n979 \r\n-366\n+299", &strict), Ok(vec![1721, 979, -366, 299]));
+        assert_eq!(parse("9000000000\n", &strict), Ok(vec![9_000_000_000]));
+        assert_eq!(
+            parse("1721\n\n979", &strict),
+            Err(ParseError { line: 2, text: "".to_string(), reason: "Blank line" }),
+        );
+        assert_eq!(
+            parse("1721\n97x9\r\n", &strict),
+            Err(ParseError { line: 2, text: "97x9".to_string(), reason: "Invalid amount" }),
+        );
+        assert_eq!(
+            parse("# ledger\n1", &strict).map_err(|err| err.to_string()),
+            Err("line 1: Unexpected comment \"# ledger\"".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_parsing_input_skipping_lines() {
+        let lenient = InputFormat { skip_blank: true, skip_comments: true };
+
+        assert_eq!(parse("# ledger\n\n1721\n  \n  # note\n979\n", &lenient), Ok(vec![1721, 979]));
+        assert_eq!(
+            parse("# ledger\n\n", &InputFormat { skip_blank: false, skip_comments: true }),
+            Err(ParseError { line: 2, text: "".to_string(), reason: "Blank line" }),
+        );
+    }
+
+    #[test]
+    fn test_pairs() {
+        assert_eq!(k_sum(TEST_INPUT, 2, 2020), vec![vec![0, 3]]);
+    }
+
+    #[test]
+    fn test_triples() {
+        assert_eq!(k_sum(TEST_INPUT, 3, 2020), vec![vec![1, 2, 4]]);
+    }
+
+    #[test]
+    fn test_no_reuse_of_entries() {
+        assert_eq!(k_sum(&[1010], 2, 2020), Vec::<Vec<usize>>::new());
+        assert_eq!(k_sum(&[1010, 5, 1010], 2, 2020), vec![vec![0, 2]]);
+    }
+
+    #[test]
+    fn test_duplicate_values() {
+        assert_eq!(
+            k_sum(&[1, 1, 1, 2, 2], 2, 3),
+            vec![
+                vec![0, 3], vec![0, 4],
+                vec![1, 3], vec![1, 4],
+                vec![2, 3], vec![2, 4],
+            ],
+        );
+        assert_eq!(k_sum(&[2, 2, 2], 2, 4), vec![vec![0, 1], vec![0, 2], vec![1, 2]]);
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let values = [5, 3, 8, 1, 9, 3, 7, 2, 6, 4, 3, 0];
+
+        for target in 0..=25 {
+            let mut expected = vec![];
+            for a in 0..values.len() {
+                for b in a + 1..values.len() {
+                    for c in b + 1..values.len() {
+                        if values[a] + values[b] + values[c] == target {
+                            expected.push(vec![a, b, c]);
+                        }
+                    }
+                }
+            }
+
+            assert_eq!(k_sum(&values, 3, target), expected, "target {}", target);
+        }
+    }
+
+    #[test]
+    fn test_negative_values() {
+        let values = [-5, 10, 3, -2, 7, 0];
+
+        assert_eq!(k_sum(&values, 2, 5), vec![vec![0, 1], vec![3, 4]]);
+        assert_eq!(k_sum(&values, 3, 0), vec![vec![0, 3, 4]]);
+        assert_eq!(k_sum(&values, 3, 10), vec![vec![2, 4, 5]]);
+    }
+
+    #[test]
+    fn test_values_near_limits() {
+        let values = [i64::MAX, i64::MIN, 1, -1, i64::MAX - 1];
+
+        assert_eq!(k_sum(&values, 2, -1), vec![vec![0, 1]]);
+        assert_eq!(k_sum(&values, 2, i64::MAX), vec![vec![2, 4]]);
+        assert_eq!(k_sum(&values, 3, i64::MAX), vec![vec![0, 2, 3]]);
+        // The sum of the two largest entries does not fit in an i64 but must
+        // not wrap around into a match.
+        assert_eq!(k_sum(&values, 2, -3), Vec::<Vec<usize>>::new());
+        assert_eq!(k_sum(&[i64::MIN, i64::MIN], 2, 0), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn test_checked_product() {
+        let values = [i64::MAX, i64::MIN, 2, -3, i64::MAX];
+
+        assert_eq!(checked_product(&values, &[0, 1]), Ok(i128::from(i64::MAX) * i128::from(i64::MIN)));
+        assert_eq!(checked_product(&values, &[2, 3]), Ok(-6));
+        assert_eq!(checked_product(&values, &[0, 1, 2]), Ok(i128::from(i64::MAX) * i128::from(i64::MIN) * 2));
+        assert_eq!(checked_product(&values, &[0, 1, 4]), Err("Product overflows 128 bits"));
+        assert_eq!(checked_product(&values, &[]), Ok(1));
+    }
+
+    fn brute_force_subsets(values: &[i64], target: i64) -> Vec<Vec<usize>> {
+        let mut subsets: Vec<Vec<usize>> = (0..1u32 << values.len())
+            .map(|mask| (0..values.len()).filter(|&i| mask & (1 << i) != 0).collect::<Vec<usize>>())
+            .filter(|subset| subset.iter().map(|&i| values[i]).sum::<i64>() == target)
+            .collect();
+        subsets.sort();
+        subsets
+    }
+
+    #[test]
+    fn test_subset_sum() {
+        let table = SubsetSum::new(TEST_INPUT, 2020).unwrap();
+
+        assert_eq!(table.count(), Some(2));
+        assert_eq!(table.all(), vec![vec![0, 3], vec![1, 2, 4]]);
+        assert_eq!(table.minimal(), Some(vec![0, 3]));
+        assert!(table.all().contains(&table.witness().unwrap()));
+
+        let table = SubsetSum::new(TEST_INPUT, 2021).unwrap();
+        assert_eq!(table.count(), Some(0));
+        assert_eq!(table.witness(), None);
+        assert_eq!(table.minimal(), None);
+        assert_eq!(table.all(), Vec::<Vec<usize>>::new());
+
+        // Without entries only the empty sum is reachable
+        let table = SubsetSum::new(&[], 5).unwrap();
+        assert_eq!(table.count(), Some(0));
+        assert_eq!(table.witness(), None);
+        assert_eq!(table.minimal(), None);
+        assert_eq!(table.all(), Vec::<Vec<usize>>::new());
+        assert_eq!(SubsetSum::new(&[], 0).unwrap().all(), vec![Vec::<usize>::new()]);
+    }
+
+    #[test]
+    fn test_subset_sum_matches_brute_force() {
+        let values = [5, 3, 8, 1, 9, 3, 7, 2, 0, 6, 4, 3];
+
+        for target in 0..=60 {
+            let expected = brute_force_subsets(&values, target);
+            let table = SubsetSum::new(&values, target).unwrap();
+
+            assert_eq!(table.count(), Some(expected.len() as u128), "target {}", target);
+            assert_eq!(table.all(), expected, "target {}", target);
+            assert_eq!(
+                table.minimal().map(|subset| subset.len()),
+                expected.iter().map(|subset| subset.len()).min(),
+                "target {}",
+                target,
+            );
+            if let Some(subset) = table.minimal() {
+                assert!(expected.contains(&subset), "target {}", target);
+            }
+            assert_eq!(table.witness().is_some(), !expected.is_empty(), "target {}", target);
+        }
+    }
+
+    #[test]
+    fn test_subset_count_overflow() {
+        // Every subset of zeros adds up to zero
+        let zeros = [0; 130];
+        assert_eq!(SubsetSum::new(&zeros[..127], 0).unwrap().count(), Some(1 << 127));
+        assert_eq!(SubsetSum::new(&zeros[..128], 0).unwrap().count(), None);
+        assert_eq!(SubsetSum::new(&zeros, 0).unwrap().count(), None);
+
+        let mut values = zeros.to_vec();
+        values.push(1);
+        assert_eq!(SubsetSum::new(&values, 1).unwrap().count(), None);
+        assert_eq!(SubsetSum::new(&values, 2).unwrap().count(), Some(0));
+    }
+
+    #[test]
+    fn test_subset_sum_rejects_negative_amounts() {
+        assert!(SubsetSum::new(&[1, -1], 0).is_err());
+        assert!(SubsetSum::new(&[1, 1], -1).is_err());
+    }
+
+    #[test]
+    fn test_subset_sum_large_input() {
+        let values: Vec<i64> = (0..2000).map(|i| (i * 7919 % 1000 + 1) * 7).collect();
+        let target = 50_001;
+        let table = SubsetSum::new(&values, target).unwrap();
+
+        let minimal = table.minimal().unwrap();
+        assert_eq!(minimal.iter().map(|&i| values[i]).sum::<i64>(), target);
+        let witness = table.witness().unwrap();
+        assert_eq!(witness.iter().map(|&i| values[i]).sum::<i64>(), target);
+    }
+
+    #[test]
+    fn test_degenerate_arity() {
+        assert_eq!(k_sum(TEST_INPUT, 0, 0), vec![Vec::<usize>::new()]);
+        assert_eq!(k_sum(TEST_INPUT, 1, 366), vec![vec![2]]);
+        assert_eq!(k_sum(TEST_INPUT, 7, 2020), Vec::<Vec<usize>>::new());
     }
 }
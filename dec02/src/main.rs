@@ -1,12 +1,34 @@
-use std::io::{self, BufRead};
+mod policy;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::ops::Range;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use policy::PolicyExpr;
 
 #[derive(Debug)]
 struct PasswordPolicy {
-    letter: char,
+    /// A single user-perceived character, which may span several `char`s.
+    letter: String,
     min: usize,
     max: usize,
 }
 
+impl fmt::Display for PasswordPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{} {}", self.min, self.max, self.letter)
+    }
+}
+
 #[derive(Debug)]
 struct Password {
     policy: PasswordPolicy,
@@ -14,65 +36,732 @@ struct Password {
 }
 
 impl Password {
-    fn is_valid(&self) -> bool {
-        let mut iter = self.value.chars();
-        let mut counter: usize = 0;
+    fn is_valid(&self, rule: &dyn PasswordPolicyRule) -> Result<bool, ValidationError> {
+        rule.is_valid(&self.policy, &self.value)
+    }
 
-        let first = &iter.nth(self.policy.min - 1).unwrap();
-        if first == &self.policy.letter {
-            counter = counter + 1;
+    fn check(&self, rule: &dyn PasswordPolicyRule) -> Result<Option<Violation>, ValidationError> {
+        rule.check(&self.policy, &self.value)
+    }
+}
+
+/// Why a password breaks a rule.
+#[derive(Debug, PartialEq)]
+enum Violation {
+    CountOutOfRange { letter: String, count: usize, min: usize, max: usize },
+    FoundAtBoth { letter: String, first: usize, second: usize },
+    FoundAtNeither { letter: String, first: usize, second: usize },
+    /// The part of a `--policy` expression that didn't hold.
+    PolicyNotSatisfied(String),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::CountOutOfRange { letter, count, min, max } => {
+                write!(f, "count {} of letter '{}' not in {}..={}", count, letter, min, max)
+            },
+            Violation::FoundAtBoth { letter, first, second } => {
+                write!(f, "letter '{}' found at both positions {} and {}", letter, first, second)
+            },
+            Violation::FoundAtNeither { letter, first, second } => {
+                write!(f, "letter '{}' found at neither position {} nor {}", letter, first, second)
+            },
+            Violation::PolicyNotSatisfied(part) => write!(f, "`{}` not satisfied", part),
         }
-        let mut iter = self.value.chars();
-        let second = &iter.nth(self.policy.max - 1).unwrap();
-        if second == &self.policy.letter {
-            counter = counter + 1;
+    }
+}
+
+/// How rules treat a policy that refers to a position outside the password.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+enum Strictness {
+    /// Out of range positions simply don't hold the letter.
+    #[default]
+    Lenient,
+    /// Out of range positions are a `ValidationError`.
+    Strict,
+}
+
+/// What the letter and the positions of a policy count in.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+enum Segmentation {
+    /// Unicode scalar values, so a combining mark is a position of its own.
+    #[default]
+    Chars,
+    /// Extended grapheme clusters, i.e. user-perceived characters.
+    Graphemes,
+    /// Grapheme clusters after NFC normalisation, so precomposed and
+    /// decomposed spellings of a character match each other.
+    NormalizedGraphemes,
+}
+
+impl Segmentation {
+    fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Segmentation::NormalizedGraphemes => Cow::Owned(text.nfc().collect()),
+            _ => Cow::Borrowed(text),
         }
+    }
 
-        counter == 1
+    fn units<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        match self {
+            Segmentation::Chars => text.char_indices()
+                .map(|(i, c)| &text[i..i + c.len_utf8()])
+                .collect(),
+            Segmentation::Graphemes | Segmentation::NormalizedGraphemes => text.graphemes(true).collect(),
+        }
     }
 }
 
-fn split(input: &str, delimiter: &str) -> Vec<String> {
-    input.split(delimiter)
-        .map(|s| s.to_string())
-        .collect()
+#[derive(Debug, Default, Copy, Clone)]
+struct RuleSettings {
+    strictness: Strictness,
+    segmentation: Segmentation,
+}
+
+#[derive(Debug, PartialEq)]
+enum ValidationError {
+    /// Positions are 1-indexed, so 0 is never in range.
+    PositionOutOfRange { position: usize, length: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::PositionOutOfRange { position, length } => {
+                write!(f, "position {} is out of range for length {}", position, length)
+            },
+        }
+    }
+}
+
+/// A way of interpreting the two numbers and the letter of a policy. New
+/// rules only need an implementation here and an entry in `rule_by_name`,
+/// and in `DEFAULT_RULES` to be checked without any `--rule`.
+trait PasswordPolicyRule: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Why `password` breaks the rule, or `None` if it follows it.
+    fn check(&self, policy: &PasswordPolicy, password: &str) -> Result<Option<Violation>, ValidationError>;
+
+    fn is_valid(&self, policy: &PasswordPolicy, password: &str) -> Result<bool, ValidationError> {
+        Ok(self.check(policy, password)?.is_none())
+    }
+
+    /// The policy as applied by this rule, for reports.
+    fn describe(&self, policy: &PasswordPolicy) -> String {
+        policy.to_string()
+    }
+}
+
+/// Part 1: the letter must occur between `min` and `max` times.
+#[derive(Default)]
+struct CountRange {
+    segmentation: Segmentation,
+}
+
+impl PasswordPolicyRule for CountRange {
+    fn name(&self) -> &'static str {
+        "count"
+    }
+
+    fn check(&self, policy: &PasswordPolicy, password: &str) -> Result<Option<Violation>, ValidationError> {
+        let password = self.segmentation.normalize(password);
+        let letter = self.segmentation.normalize(&policy.letter);
+        let count = self.segmentation.units(&password)
+            .into_iter()
+            .filter(|unit| unit == &letter)
+            .count();
+
+        if (policy.min..=policy.max).contains(&count) {
+            Ok(None)
+        } else {
+            Ok(Some(Violation::CountOutOfRange {
+                letter: policy.letter.clone(),
+                count,
+                min: policy.min,
+                max: policy.max,
+            }))
+        }
+    }
+}
+
+/// Part 2: the letter must be at exactly one of the (1-indexed) positions
+/// `min` and `max`.
+#[derive(Default)]
+struct PositionXor {
+    strictness: Strictness,
+    segmentation: Segmentation,
+}
+
+impl PositionXor {
+    fn has_letter_at(&self, letter: &str, units: &[&str], position: usize) -> Result<bool, ValidationError> {
+        let found = position.checked_sub(1)
+            .and_then(|index| units.get(index));
+
+        match (found, self.strictness) {
+            (Some(unit), _) => Ok(*unit == letter),
+            (None, Strictness::Lenient) => Ok(false),
+            (None, Strictness::Strict) => Err(ValidationError::PositionOutOfRange {
+                position,
+                length: units.len(),
+            }),
+        }
+    }
+}
+
+impl PasswordPolicyRule for PositionXor {
+    fn name(&self) -> &'static str {
+        "position"
+    }
+
+    fn check(&self, policy: &PasswordPolicy, password: &str) -> Result<Option<Violation>, ValidationError> {
+        let password = self.segmentation.normalize(password);
+        let letter = self.segmentation.normalize(&policy.letter);
+        let units = self.segmentation.units(&password);
+
+        let first = self.has_letter_at(&letter, &units, policy.min)?;
+        let second = self.has_letter_at(&letter, &units, policy.max)?;
+
+        let letter = policy.letter.clone();
+        match (first, second) {
+            (true, true) => Ok(Some(Violation::FoundAtBoth { letter, first: policy.min, second: policy.max })),
+            (false, false) => Ok(Some(Violation::FoundAtNeither { letter, first: policy.min, second: policy.max })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A rule written in the policy language of the `policy` module. It applies
+/// the same expression to every password and ignores the per-line policy.
+struct CustomPolicy {
+    expr: PolicyExpr,
+    settings: RuleSettings,
+}
+
+impl PasswordPolicyRule for CustomPolicy {
+    fn name(&self) -> &'static str {
+        "policy"
+    }
+
+    fn check(&self, _policy: &PasswordPolicy, password: &str) -> Result<Option<Violation>, ValidationError> {
+        Ok(self.expr.violation(password, &self.settings)?
+            .map(|part| Violation::PolicyNotSatisfied(part.to_string())))
+    }
+
+    fn is_valid(&self, _policy: &PasswordPolicy, password: &str) -> Result<bool, ValidationError> {
+        self.expr.evaluate(password, &self.settings)
+    }
+
+    fn describe(&self, _policy: &PasswordPolicy) -> String {
+        self.expr.to_string()
+    }
+}
+
+/// The rules checked when neither `--rule` nor `--policy` is given.
+const DEFAULT_RULES: &[&str] = &["count", "position"];
+
+fn rule_by_name(name: &str, settings: RuleSettings) -> Option<Box<dyn PasswordPolicyRule>> {
+    match name {
+        "count" => Some(Box::new(CountRange {
+            segmentation: settings.segmentation,
+        })),
+        "position" => Some(Box::new(PositionXor {
+            strictness: settings.strictness,
+            segmentation: settings.segmentation,
+        })),
+        _ => None,
+    }
+}
+
+/// How `--report` prints one row per password and rule on stdout, and one
+/// per line that fails to parse.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum ReportFormat {
+    JsonLines,
+    Csv,
+}
+
+const CSV_HEADER: &str = "line,rule,password,policy,verdict,reason";
+
+impl ReportFormat {
+    fn row(
+        &self,
+        line: usize,
+        rule: &dyn PasswordPolicyRule,
+        password: &Password,
+        verdict: &Result<Option<Violation>, ValidationError>,
+    ) -> String {
+        let (verdict, reason) = match verdict {
+            Ok(None) => ("valid", None),
+            Ok(Some(violation)) => ("invalid", Some(violation.to_string())),
+            Err(err) => ("error", Some(err.to_string())),
+        };
+        let policy = rule.describe(&password.policy);
+
+        match self {
+            ReportFormat::JsonLines => format!(
+                "{{\"line\":{},\"rule\":{},\"password\":{},\"policy\":{},\"verdict\":{},\"reason\":{}}}",
+                line,
+                json_string(rule.name()),
+                json_string(&password.value),
+                json_string(&policy),
+                json_string(verdict),
+                reason.map_or("null".to_string(), |reason| json_string(&reason)),
+            ),
+            ReportFormat::Csv => format!(
+                "{},{},{},{},{},{}",
+                line,
+                csv_field(rule.name()),
+                csv_field(&password.value),
+                csv_field(&policy),
+                verdict,
+                csv_field(reason.as_deref().unwrap_or("")),
+            ),
+        }
+    }
+
+    /// The row for a line that isn't a password with a policy, so that the
+    /// report accounts for every line. Only the verdict and reason are set.
+    fn unparsable_row(&self, line: usize, text: &str, err: &PolicyParseError) -> String {
+        let reason = format!("{} in {:?}", err, text);
+
+        match self {
+            ReportFormat::JsonLines => format!(
+                "{{\"line\":{},\"rule\":null,\"password\":null,\"policy\":null,\"verdict\":\"error\",\"reason\":{}}}",
+                line,
+                json_string(&reason),
+            ),
+            ReportFormat::Csv => format!("{},,,,error,{}", line, csv_field(&reason)),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+struct Options {
+    rules: Vec<Box<dyn PasswordPolicyRule>>,
+    report: Option<ReportFormat>,
+    /// Worker threads; 1 validates on the reading thread.
+    jobs: usize,
+}
+
+impl Options {
+    /// Rules named by `--rule NAME` arguments and written as `--policy EXPR`,
+    /// or every named rule if neither is given. `--strict` turns out of range
+    /// positions into errors, `--graphemes` counts user-perceived characters
+    /// and `--nfc` additionally normalises. `--report json|csv` prints a
+    /// verdict for every password instead of listing the valid ones and
+    /// `--jobs N` validates on N threads.
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut report = None;
+        let mut jobs = 1;
+        let mut names = vec![];
+        let mut policies = vec![];
+        let mut settings = RuleSettings::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rule" | "-r" => {
+                    let name = args.next().ok_or("Missing value for --rule")?;
+                    if rule_by_name(&name, settings).is_none() {
+                        return Err("Unknown rule".to_string());
+                    }
+                    names.push(name);
+                },
+                "--policy" | "-p" => {
+                    let policy = args.next().ok_or("Missing value for --policy")?;
+                    policies.push(policy.parse::<PolicyExpr>().map_err(|err| err.to_string())?);
+                },
+                "--report" => {
+                    report = match args.next().ok_or("Missing value for --report")?.as_str() {
+                        "json" => Some(ReportFormat::JsonLines),
+                        "csv" => Some(ReportFormat::Csv),
+                        _ => return Err("Unknown report format".to_string()),
+                    };
+                },
+                "--jobs" | "-j" => {
+                    jobs = args.next()
+                        .ok_or("Missing value for --jobs")?
+                        .parse()
+                        .ok()
+                        .filter(|&jobs| jobs > 0)
+                        .ok_or("Invalid value for --jobs")?;
+                },
+                "--strict" => settings.strictness = Strictness::Strict,
+                "--graphemes" => settings.segmentation = Segmentation::Graphemes,
+                "--nfc" => settings.segmentation = Segmentation::NormalizedGraphemes,
+                _ => return Err("Unknown argument".to_string()),
+            }
+        }
+
+        if names.is_empty() && policies.is_empty() {
+            names = DEFAULT_RULES.iter().map(|name| name.to_string()).collect();
+        }
+
+        // Built only now, as the settings may follow the names
+        let mut rules: Vec<Box<dyn PasswordPolicyRule>> = names.iter()
+            .map(|name| rule_by_name(name, settings).ok_or_else(|| "Unknown rule".to_string()))
+            .collect::<Result<_, _>>()?;
+        for expr in policies {
+            rules.push(Box::new(CustomPolicy { expr, settings }));
+        }
+
+        Ok(Options { rules, report, jobs })
+    }
+}
+
+/// Why a `"min-max letter: password"` line could not be parsed. Each variant
+/// carries the byte span of the offending part of the line.
+#[derive(Debug, PartialEq)]
+enum PolicyParseError {
+    MissingColon(Range<usize>),
+    BadRange(Range<usize>),
+    MinGreaterThanMax(Range<usize>),
+    EmptyLetter(Range<usize>),
+    /// Anything between the letter and the colon
+    TrailingCharacters(Range<usize>),
+    NonNumericBound(Range<usize>),
+}
+
+impl PolicyParseError {
+    fn span(&self) -> Range<usize> {
+        match self {
+            PolicyParseError::MissingColon(span)
+            | PolicyParseError::BadRange(span)
+            | PolicyParseError::MinGreaterThanMax(span)
+            | PolicyParseError::EmptyLetter(span)
+            | PolicyParseError::TrailingCharacters(span)
+            | PolicyParseError::NonNumericBound(span) => span.clone(),
+        }
+    }
+}
+
+impl fmt::Display for PolicyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            PolicyParseError::MissingColon(_) => "missing colon",
+            PolicyParseError::BadRange(_) => "bad range",
+            PolicyParseError::MinGreaterThanMax(_) => "min is greater than max",
+            PolicyParseError::EmptyLetter(_) => "empty letter",
+            PolicyParseError::TrailingCharacters(_) => "trailing characters after letter",
+            PolicyParseError::NonNumericBound(_) => "non-numeric bound",
+        };
+        let span = self.span();
+
+        write!(f, "{} at {}..{}", reason, span.start, span.end)
+    }
+}
+
+fn parse_bound(input: &str, offset: usize) -> Result<usize, PolicyParseError> {
+    input.parse::<usize>()
+        .map_err(|_| PolicyParseError::NonNumericBound(offset..offset + input.len()))
 }
 
 impl std::str::FromStr for Password {
-    type Err = &'static str;
+    type Err = PolicyParseError;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<String> = split(input, ": ");
-        let policy_parts = split(&parts[0], " ");
-        let length_parts = split(&policy_parts[0], "-");
+        let colon = input.find(':')
+            .ok_or(PolicyParseError::MissingColon(0..input.len()))?;
+        let policy = &input[..colon];
+        let value = &input[colon + 1..];
+        let value = value.strip_prefix(' ').unwrap_or(value);
+
+        let (range, letter_start) = match policy.find(' ') {
+            Some(space) => (&policy[..space], space + 1),
+            None => (policy, colon),
+        };
+
+        let dash = range.find('-')
+            .ok_or(PolicyParseError::BadRange(0..range.len()))?;
+        let min = parse_bound(&range[..dash], 0)?;
+        let max = parse_bound(&range[dash + 1..], dash + 1)?;
+        if min > max {
+            return Err(PolicyParseError::MinGreaterThanMax(0..range.len()));
+        }
+
+        let letter = policy[letter_start..].graphemes(true)
+            .next()
+            .ok_or(PolicyParseError::EmptyLetter(letter_start..colon))?
+            .to_string();
+        let letter_end = letter_start + letter.len();
+        if letter_end < colon {
+            return Err(PolicyParseError::TrailingCharacters(letter_end..colon));
+        }
 
         Ok(Password {
-            value: parts[1].to_string(),
+            value: value.to_string(),
             policy: PasswordPolicy {
-                letter: policy_parts[1].chars().nth(0).unwrap(),
-                min: length_parts[0].parse::<usize>().unwrap(),
-                max: length_parts[1].parse::<usize>().unwrap(),
+                letter,
+                min,
+                max,
             },
         })
     }
 }
 
+/// Number of lines handed to a worker at a time when validating in parallel.
+const BATCH_SIZE: usize = 4096;
+
+/// What validating a run of consecutive lines produced, kept in input order
+/// so parallel batches can be written out as if validated sequentially.
+struct BatchOutput {
+    stdout: String,
+    stderr: String,
+    lines: usize,
+    skipped: usize,
+    valid: Vec<usize>,
+}
+
+impl BatchOutput {
+    fn new(rules: usize) -> Self {
+        BatchOutput {
+            stdout: String::new(),
+            stderr: String::new(),
+            lines: 0,
+            skipped: 0,
+            valid: vec![0; rules],
+        }
+    }
+
+    /// Parses and validates one input line against every rule.
+    fn validate_line(&mut self, number: usize, line: &str, rules: &[Box<dyn PasswordPolicyRule>], report: Option<ReportFormat>) {
+        self.lines += 1;
+
+        let password: Password = match line.parse() {
+            Ok(password) => password,
+            Err(err) => {
+                self.skipped += 1;
+                let _ = writeln!(self.stderr, "line {}: {} in {:?}, skipping", number, err, line);
+                if let Some(format) = report {
+                    let _ = writeln!(self.stdout, "{}", format.unparsable_row(number, line, &err));
+                }
+                return;
+            },
+        };
+
+        for (rule, valid) in rules.iter().zip(self.valid.iter_mut()) {
+            if let Some(format) = report {
+                let verdict = password.check(rule.as_ref());
+                if let Ok(None) = verdict {
+                    *valid += 1;
+                }
+                let _ = writeln!(self.stdout, "{}", format.row(number, rule.as_ref(), &password, &verdict));
+                continue;
+            }
+
+            match password.is_valid(rule.as_ref()) {
+                Ok(true) => {
+                    *valid += 1;
+                    let _ = writeln!(self.stderr, "{}: {:?} - letter: {} ({}, {})", rule.name(), password.value, password.policy.letter, password.policy.min, password.policy.max);
+                },
+                Ok(false) => {},
+                Err(err) => {
+                    let _ = writeln!(self.stderr, "{}: {:?} - {}", rule.name(), password.value, err);
+                },
+            }
+        }
+    }
+
+    fn write(&self) {
+        io::stdout().lock().write_all(self.stdout.as_bytes()).unwrap();
+        io::stderr().lock().write_all(self.stderr.as_bytes()).unwrap();
+    }
+
+    /// Adds the counters of `other` to these running totals.
+    fn add(&mut self, other: &BatchOutput) {
+        self.lines += other.lines;
+        self.skipped += other.skipped;
+        for (total, valid) in self.valid.iter_mut().zip(&other.valid) {
+            *total += valid;
+        }
+    }
+}
+
+/// Validates `lines` one at a time, so memory use doesn't grow with the
+/// input. Output is passed to `emit` every `BATCH_SIZE` lines and the totals
+/// returned.
+fn validate_sequential<I, F>(
+    lines: I,
+    rules: &[Box<dyn PasswordPolicyRule>],
+    report: Option<ReportFormat>,
+    mut emit: F,
+) -> BatchOutput
+where
+    I: Iterator<Item = (usize, String)>,
+    F: FnMut(&BatchOutput),
+{
+    let mut totals = BatchOutput::new(rules.len());
+    let mut output = BatchOutput::new(rules.len());
+
+    for (number, line) in lines {
+        output.validate_line(number, &line, rules, report);
+
+        if output.lines == BATCH_SIZE {
+            emit(&output);
+            totals.add(&output);
+            output = BatchOutput::new(rules.len());
+        }
+    }
+
+    emit(&output);
+    totals.add(&output);
+    totals
+}
+
+/// Validates `lines` in batches on `jobs` worker threads. Batches reach
+/// `emit` in input order. At most `2 * jobs` batches are read but not yet
+/// emitted, so a slow batch holds back the reader instead of piling up the
+/// ones after it.
+fn validate_parallel<I, F>(
+    lines: I,
+    jobs: usize,
+    rules: &[Box<dyn PasswordPolicyRule>],
+    report: Option<ReportFormat>,
+    mut emit: F,
+) -> BatchOutput
+where
+    I: Iterator<Item = (usize, String)>,
+    F: FnMut(&BatchOutput) + Send,
+{
+    let (batch_sender, batch_receiver) = mpsc::sync_channel::<(usize, Vec<(usize, String)>)>(jobs);
+    let batch_receiver = Mutex::new(batch_receiver);
+    let (output_sender, output_receiver) = mpsc::sync_channel::<(usize, BatchOutput)>(jobs);
+    // One message for every batch emitted, which lets the reader go on
+    let (emitted_sender, emitted_receiver) = mpsc::channel::<()>();
+    let window = 2 * jobs;
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let batch_receiver = &batch_receiver;
+            let output_sender = output_sender.clone();
+
+            scope.spawn(move || loop {
+                let received = batch_receiver.lock().unwrap().recv();
+                let (sequence, batch) = match received {
+                    Ok(batch) => batch,
+                    Err(_) => return,
+                };
+
+                let mut output = BatchOutput::new(rules.len());
+                for (number, line) in &batch {
+                    output.validate_line(*number, line, rules, report);
+                }
+                if output_sender.send((sequence, output)).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(output_sender);
+
+        let writer = scope.spawn(move || {
+            let mut totals = BatchOutput::new(rules.len());
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+
+            for (sequence, output) in output_receiver {
+                pending.insert(sequence, output);
+                while let Some(output) = pending.remove(&next) {
+                    emit(&output);
+                    totals.add(&output);
+                    next += 1;
+                    let _ = emitted_sender.send(());
+                }
+            }
+
+            totals
+        });
+
+        let mut sequence = 0;
+        let mut send = |batch| {
+            if sequence >= window {
+                // Batch `sequence - window` has to be emitted first
+                let _ = emitted_receiver.recv();
+            }
+            batch_sender.send((sequence, batch)).unwrap();
+            sequence += 1;
+        };
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for line in lines {
+            batch.push(line);
+            if batch.len() == BATCH_SIZE {
+                send(mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE)));
+            }
+        }
+        if !batch.is_empty() {
+            send(batch);
+        }
+        drop(batch_sender);
+
+        writer.join().unwrap()
+    })
+}
+
 fn main() {
+    let Options { rules, report, jobs } = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!(
+                "{}\nusage: dec02 [--rule count|position]... [--policy EXPR]... [--strict] \
+                 [--graphemes|--nfc] [--report json|csv] [--jobs N] < passwords",
+                err,
+            );
+            std::process::exit(2);
+        },
+    };
+
+    if report == Some(ReportFormat::Csv) {
+        println!("{}", CSV_HEADER);
+    }
+
     let stdin = io::stdin();
-    let passwords: Vec<Password> = stdin.lock()
+    let lines = stdin.lock()
         .lines()
-        .map(|value| value.unwrap().parse().unwrap())
-        .collect();
+        .enumerate()
+        .map_while(|(i, line)| match line {
+            Ok(line) => Some((i + 1, line)),
+            Err(err) => {
+                eprintln!("line {}: {}, stopping", i + 1, err);
+                None
+            },
+        });
 
-    let valid_passwords: Vec<Password> = passwords.into_iter()
-        .filter(|password| password.is_valid())
-        .collect();
+    let totals = if jobs > 1 {
+        validate_parallel(lines, jobs, &rules, report, BatchOutput::write)
+    } else {
+        validate_sequential(lines, &rules, report, BatchOutput::write)
+    };
 
-    for password in &valid_passwords {
-        eprintln!("{:?} - letter: {} ({}, {})", password.value, password.policy.letter, password.policy.min, password.policy.max);
+    eprintln!("lines: {}, skipped: {}", totals.lines, totals.skipped);
+    for (rule, valid) in rules.iter().zip(totals.valid) {
+        eprintln!("{}: {}", rule.name(), valid);
     }
-
-    eprintln!("{}", valid_passwords.len());
 }
 
 #[cfg(test)]
@@ -82,9 +771,10 @@ mod test {
     #[test]
     fn test_validate_password() {
         let valid_password: Password = "2-9 c: ccccccccc".parse().unwrap();
-        assert_eq!(valid_password.is_valid(), false);
+        assert_eq!(valid_password.is_valid(&PositionXor::default()), Ok(false));
+        assert_eq!(valid_password.is_valid(&CountRange::default()), Ok(true));
         assert_eq!(valid_password.value, "ccccccccc");
-        assert_eq!(valid_password.policy.letter, 'c');
+        assert_eq!(valid_password.policy.letter, "c");
         assert_eq!(valid_password.policy.min, 2);
         assert_eq!(valid_password.policy.max, 9);
 
@@ -93,10 +783,330 @@ mod test {
             policy: PasswordPolicy {
                 min: 1,
                 max: 3,
-                letter: 'b',
+                letter: "b".to_string(),
             }
         };
 
-        assert_eq!(invalid_password.is_valid(), false);
+        assert_eq!(invalid_password.is_valid(&PositionXor::default()), Ok(false));
+        assert_eq!(invalid_password.is_valid(&CountRange::default()), Ok(false));
+    }
+
+    #[test]
+    fn test_rules() {
+        let passwords: Vec<Password> = ["1-3 a: abcde", "1-3 b: cdefg", "2-9 c: ccccccccc"]
+            .iter()
+            .map(|line| line.parse().unwrap())
+            .collect();
+
+        let valid = |rule: &dyn PasswordPolicyRule| passwords.iter()
+            .map(|password| password.is_valid(rule).unwrap())
+            .collect::<Vec<bool>>();
+
+        assert_eq!(valid(&CountRange::default()), vec![true, false, true]);
+        assert_eq!(valid(&PositionXor::default()), vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_grapheme_segmentation() {
+        // "e" followed by a combining acute accent, and the precomposed "é"
+        let decomposed = "e\u{301}";
+        let line = format!("2-3 {}: x{}y\u{e9}", decomposed, decomposed);
+        let password: Password = line.parse().unwrap();
+        assert_eq!(password.policy.letter, decomposed);
+
+        let position = |segmentation| password.is_valid(&PositionXor { segmentation, ..PositionXor::default() });
+        let count = |segmentation| password.is_valid(&CountRange { segmentation });
+
+        // By chars the accent lands on position 3 and the letter never matches
+        assert_eq!(position(Segmentation::Chars), Ok(false));
+        assert_eq!(position(Segmentation::Graphemes), Ok(true));
+        assert_eq!(position(Segmentation::NormalizedGraphemes), Ok(true));
+
+        assert_eq!(count(Segmentation::Chars), Ok(false));
+        assert_eq!(count(Segmentation::Graphemes), Ok(false));
+        assert_eq!(count(Segmentation::NormalizedGraphemes), Ok(true));
+
+        let flag: Password = "1-1 \u{1f1f8}\u{1f1ea}: \u{1f1f8}\u{1f1ea}!".parse().unwrap();
+        assert_eq!(flag.is_valid(&PositionXor { segmentation: Segmentation::Graphemes, ..PositionXor::default() }), Ok(false));
+        assert_eq!(flag.is_valid(&CountRange { segmentation: Segmentation::Graphemes }), Ok(true));
+    }
+
+    #[test]
+    fn test_out_of_range_positions() {
+        let lenient = PositionXor::default();
+        let strict = PositionXor { strictness: Strictness::Strict, ..PositionXor::default() };
+        let password = |min, max| Password {
+            value: "abc".to_string(),
+            policy: PasswordPolicy { letter: "a".to_string(), min, max },
+        };
+
+        assert_eq!(password(1, 4).is_valid(&lenient), Ok(true));
+        assert_eq!(password(0, 1).is_valid(&lenient), Ok(true));
+        assert_eq!(password(0, 9).is_valid(&lenient), Ok(false));
+        assert_eq!(
+            password(1, 4).is_valid(&strict),
+            Err(ValidationError::PositionOutOfRange { position: 4, length: 3 }),
+        );
+        assert_eq!(
+            password(0, 1).is_valid(&strict),
+            Err(ValidationError::PositionOutOfRange { position: 0, length: 3 }),
+        );
+        assert_eq!(password(1, 3).is_valid(&strict), Ok(true));
+    }
+
+    /// Small xorshift generator, so the property tests are reproducible
+    /// without pulling in a dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    #[test]
+    fn test_validation_never_panics() {
+        const ALPHABET: &[char] = &['a', 'b', 'é', 'ß', '漢', '\u{301}', '🦀'];
+        let mut rng = Rng(0x2020_1202);
+
+        for _ in 0..20_000 {
+            let length = rng.next(8);
+            let value: String = (0..length).map(|_| ALPHABET[rng.next(ALPHABET.len())]).collect();
+            let bound = |rng: &mut Rng| match rng.next(4) {
+                0 => 0,
+                1 => usize::MAX - rng.next(2),
+                _ => rng.next(12),
+            };
+            let policy = PasswordPolicy {
+                letter: ALPHABET[rng.next(ALPHABET.len())].to_string(),
+                min: bound(&mut rng),
+                max: bound(&mut rng),
+            };
+
+            let chars: Vec<char> = value.chars().collect();
+            let at = |position: usize| position.checked_sub(1)
+                .and_then(|i| chars.get(i))
+                .is_some_and(|c| c.to_string() == policy.letter);
+            let in_range = |position: usize| position >= 1 && position <= chars.len();
+
+            let lenient = PositionXor::default();
+            let strict = PositionXor { strictness: Strictness::Strict, ..PositionXor::default() };
+
+            assert_eq!(lenient.is_valid(&policy, &value), Ok(at(policy.min) != at(policy.max)));
+            assert_eq!(
+                strict.is_valid(&policy, &value).is_ok(),
+                in_range(policy.min) && in_range(policy.max),
+                "{:?} {:?}", policy, value,
+            );
+            assert!(CountRange::default().is_valid(&policy, &value).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_options_from_args() {
+        fn parse_args(args: &[&str]) -> Result<Options, String> {
+            Options::from_args(args.iter().map(|s| s.to_string()))
+        }
+
+        fn names(args: &[&str]) -> Result<Vec<&'static str>, String> {
+            parse_args(args).map(|options| options.rules.iter().map(|rule| rule.name()).collect())
+        }
+
+        assert_eq!(names(&[]), Ok(vec!["count", "position"]));
+        assert!(DEFAULT_RULES.iter().all(|name| rule_by_name(name, RuleSettings::default()).is_some()));
+        assert_eq!(names(&["--rule", "position", "--strict"]), Ok(vec!["position"]));
+        assert_eq!(names(&["--rule", "position"]), Ok(vec!["position"]));
+        assert_eq!(names(&["--rule", "xor"]), Err("Unknown rule".to_string()));
+        assert_eq!(names(&["--rule"]), Err("Missing value for --rule".to_string()));
+        assert_eq!(names(&["--policy", "len>=8", "-r", "count"]), Ok(vec!["count", "policy"]));
+        assert_eq!(names(&["--policy", "len>8"]), Err("Expected '>=', '<=' or '=' at 3".to_string()));
+        assert_eq!(names(&["--policy", "1-3 a |b"]), Err("Expected 'and', 'or' or end of policy at 6".to_string()));
+
+        assert_eq!(parse_args(&[]).map(|options| options.report), Ok(None));
+        assert_eq!(parse_args(&["--report", "csv"]).map(|options| options.report), Ok(Some(ReportFormat::Csv)));
+        assert_eq!(parse_args(&["--report", "xml"]).map(|options| options.report), Err("Unknown report format".to_string()));
+
+        assert_eq!(parse_args(&[]).map(|options| options.jobs), Ok(1));
+        assert_eq!(parse_args(&["--jobs", "4"]).map(|options| options.jobs), Ok(4));
+        assert_eq!(parse_args(&["-j", "2", "--strict"]).map(|options| options.jobs), Ok(2));
+        assert_eq!(parse_args(&["--jobs", "0"]).map(|options| options.jobs), Err("Invalid value for --jobs".to_string()));
+        assert_eq!(parse_args(&["--jobs", "many"]).map(|options| options.jobs), Err("Invalid value for --jobs".to_string()));
+        assert_eq!(parse_args(&["--jobs"]).map(|options| options.jobs), Err("Missing value for --jobs".to_string()));
+    }
+
+    #[test]
+    fn test_parallel_validation_keeps_order() {
+        let options = Options::from_args(["--report", "json", "--policy", "len>=5"].iter().map(|s| s.to_string())).unwrap();
+        let lines = || (1..=3 * BATCH_SIZE + 17).map(|number| {
+            let line = match number % 5 {
+                0 => "not a policy".to_string(),
+                n => format!("1-{} a: {}", n, "a".repeat(number % 7)),
+            };
+            (number, line)
+        });
+
+        let mut expected = (String::new(), String::new());
+        let sequential = validate_sequential(lines(), &options.rules, options.report, |output| {
+            expected.0.push_str(&output.stdout);
+            expected.1.push_str(&output.stderr);
+        });
+
+        let mut actual = (String::new(), String::new());
+        let parallel = validate_parallel(lines(), 4, &options.rules, options.report, |output| {
+            actual.0.push_str(&output.stdout);
+            actual.1.push_str(&output.stderr);
+        });
+
+        assert_eq!(actual, expected);
+        assert_eq!(parallel.lines, 3 * BATCH_SIZE + 17);
+        assert_eq!(parallel.lines, sequential.lines);
+        assert_eq!(parallel.skipped, sequential.skipped);
+        assert_eq!(parallel.valid, sequential.valid);
+    }
+
+    #[test]
+    fn test_parallel_validation_reads_ahead_boundedly() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        /// Holds up the first batch, so every later one is done before it.
+        struct Stall;
+
+        impl PasswordPolicyRule for Stall {
+            fn name(&self) -> &'static str {
+                "stall"
+            }
+
+            fn check(&self, _: &PasswordPolicy, password: &str) -> Result<Option<Violation>, ValidationError> {
+                if password == "slow" {
+                    thread::sleep(Duration::from_millis(300));
+                }
+                Ok(None)
+            }
+        }
+
+        let rules: Vec<Box<dyn PasswordPolicyRule>> = vec![Box::new(Stall)];
+        let read = AtomicUsize::new(0);
+        let lines = (1..=40 * BATCH_SIZE).map(|number| {
+            read.fetch_add(1, Ordering::SeqCst);
+            let password = if number == 1 { "slow" } else { "fast" };
+            (number, format!("1-3 a: {}", password))
+        });
+
+        let jobs = 2;
+        let (mut emitted, mut ahead) = (0, 0);
+        let totals = validate_parallel(lines, jobs, &rules, None, |output| {
+            emitted += output.lines;
+            ahead = ahead.max(read.load(Ordering::SeqCst) - emitted);
+        });
+        assert_eq!(totals.lines, 40 * BATCH_SIZE);
+        // The batches in the window plus the one being filled
+        assert!(ahead <= (2 * jobs + 1) * BATCH_SIZE, "{} lines ahead", ahead);
+    }
+
+    #[test]
+    fn test_violations() {
+        let check = |line: &str, rule: &dyn PasswordPolicyRule| {
+            line.parse::<Password>().unwrap().check(rule).unwrap().map(|violation| violation.to_string())
+        };
+
+        assert_eq!(check("1-3 a: abcde", &CountRange::default()), None);
+        assert_eq!(
+            check("1-3 b: cdefg", &CountRange::default()),
+            Some("count 0 of letter 'b' not in 1..=3".to_string()),
+        );
+        assert_eq!(
+            check("2-9 c: ccccccccc", &PositionXor::default()),
+            Some("letter 'c' found at both positions 2 and 9".to_string()),
+        );
+        assert_eq!(
+            check("1-3 b: cdefg", &PositionXor::default()),
+            Some("letter 'b' found at neither position 1 nor 3".to_string()),
+        );
+
+        let policy = CustomPolicy {
+            expr: "len>=8 and 1- [0-9]".parse().unwrap(),
+            settings: RuleSettings::default(),
+        };
+        assert_eq!(check("1-3 a: abcdefgh", &policy), Some("`1- [0-9]` not satisfied".to_string()));
+    }
+
+    #[test]
+    fn test_report_rows() {
+        let password: Password = "1-3 b: c,\"d\"".parse().unwrap();
+        let rule = CountRange::default();
+        let verdict = password.check(&rule);
+
+        assert_eq!(
+            ReportFormat::JsonLines.row(4, &rule, &password, &verdict),
+            r#"{"line":4,"rule":"count","password":"c,\"d\"","policy":"1-3 b","verdict":"invalid","reason":"count 0 of letter 'b' not in 1..=3"}"#,
+        );
+        assert_eq!(
+            ReportFormat::Csv.row(4, &rule, &password, &verdict),
+            r#"4,count,"c,""d""",1-3 b,invalid,count 0 of letter 'b' not in 1..=3"#,
+        );
+
+        let password: Password = "1-3 c: c\tx".parse().unwrap();
+        let rule = PositionXor { strictness: Strictness::Strict, ..PositionXor::default() };
+        let verdict = password.check(&rule);
+        assert_eq!(
+            ReportFormat::JsonLines.row(1, &rule, &password, &verdict),
+            r#"{"line":1,"rule":"position","password":"c\tx","policy":"1-3 c","verdict":"valid","reason":null}"#,
+        );
+
+        // Lines that don't parse get a row of their own
+        let err = "1-3 a, b".parse::<Password>().unwrap_err();
+        assert_eq!(
+            ReportFormat::JsonLines.unparsable_row(7, "1-3 a, b", &err),
+            r#"{"line":7,"rule":null,"password":null,"policy":null,"verdict":"error","reason":"missing colon at 0..8 in \"1-3 a, b\""}"#,
+        );
+        assert_eq!(
+            ReportFormat::Csv.unparsable_row(7, "1-3 a, b", &err),
+            r#"7,,,,error,"missing colon at 0..8 in ""1-3 a, b""""#,
+        );
+
+        let options = Options::from_args(["--report", "csv", "-r", "count"].iter().map(|s| s.to_string())).unwrap();
+        let mut output = BatchOutput::new(options.rules.len());
+        for (number, line) in ["1-3 a: abc", "oops", "2-1 a: aa"].iter().enumerate() {
+            output.validate_line(number + 1, line, &options.rules, options.report);
+        }
+        assert_eq!(
+            output.stdout,
+            "1,count,abc,1-3 a,valid,\n\
+             2,,,,error,\"missing colon at 0..4 in \"\"oops\"\"\"\n\
+             3,,,,error,\"min is greater than max at 0..3 in \"\"2-1 a: aa\"\"\"\n",
+        );
+        assert_eq!(output.skipped, 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        fn parse(input: &str) -> Result<Password, PolicyParseError> {
+            input.parse()
+        }
+
+        assert_eq!(parse("1-3 a abcde").unwrap_err(), PolicyParseError::MissingColon(0..11));
+        assert_eq!(parse("13 a: abcde").unwrap_err(), PolicyParseError::BadRange(0..2));
+        assert_eq!(parse("3-1 a: abcde").unwrap_err(), PolicyParseError::MinGreaterThanMax(0..3));
+        assert_eq!(parse("1-3 : abcde").unwrap_err(), PolicyParseError::EmptyLetter(4..4));
+        assert_eq!(parse("1-3: abcde").unwrap_err(), PolicyParseError::EmptyLetter(3..3));
+        assert_eq!(parse("1-3 ab: aaa").unwrap_err(), PolicyParseError::TrailingCharacters(5..6));
+        assert_eq!(parse("1-3 a : aaa").unwrap_err(), PolicyParseError::TrailingCharacters(5..6));
+        assert_eq!(parse("1-3 e\u{301}x: aaa").unwrap_err(), PolicyParseError::TrailingCharacters(7..8));
+        assert_eq!(parse("x-3 a: abcde").unwrap_err(), PolicyParseError::NonNumericBound(0..1));
+        assert_eq!(parse("1-3x b: abcde").unwrap_err(), PolicyParseError::NonNumericBound(2..4));
+        assert_eq!(parse("").unwrap_err(), PolicyParseError::MissingColon(0..0));
+
+        assert_eq!(
+            parse("1-3 a abcde").unwrap_err().to_string(),
+            "missing colon at 0..11",
+        );
+
+        let password = parse("1-3 b: cdefg").unwrap();
+        assert_eq!(password.value, "cdefg");
+        assert_eq!((password.policy.letter.as_str(), password.policy.min, password.policy.max), ("b", 1, 3));
     }
 }
@@ -0,0 +1,526 @@
+//! A small language for password policies that don't fit the puzzle's
+//! `min-max letter` form.
+//!
+//! ```text
+//! expr  := and ("or" and)*
+//! and   := unary ("and" unary)*
+//! unary := "not" unary | "(" expr ")" | atom
+//! atom  := MIN "-" MAX set          number of characters in set within MIN..=MAX
+//!        | "at" POS "," POS set      exactly one of the positions is in set
+//!        | "len" (">=" | "<=" | "=") N
+//! set   := item ("|" item)*
+//! item  := CHARACTER | "[" (CHARACTER | CHARACTER "-" CHARACTER)+ "]"
+//! ```
+//!
+//! For example `1-3 a|b`, `1- [0-9] and len>=12` or
+//! `not (at 1,2 [A-Z]) or 2-99 [!-/]`. Keywords are case-insensitive.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{RuleSettings, Strictness, ValidationError};
+
+#[derive(Debug, PartialEq)]
+pub enum SetItem {
+    /// A single user-perceived character.
+    Literal(String),
+    /// Inclusive `char` ranges, as in `[a-z0-9_]`.
+    Class(Vec<RangeInclusive<char>>),
+}
+
+/// Characters an atom looks for, e.g. `a|b|[0-9]`.
+#[derive(Debug, PartialEq)]
+pub struct CharSet(Vec<SetItem>);
+
+impl fmt::Display for CharSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, item) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "|")?;
+            }
+            match item {
+                SetItem::Literal(literal) => write!(f, "{}", literal)?,
+                SetItem::Class(ranges) => {
+                    write!(f, "[")?;
+                    for range in ranges {
+                        if range.start() == range.end() {
+                            write!(f, "{}", range.start())?;
+                        } else {
+                            write!(f, "{}-{}", range.start(), range.end())?;
+                        }
+                    }
+                    write!(f, "]")?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CharSet {
+    fn matches(&self, unit: &str, settings: &RuleSettings) -> bool {
+        self.0.iter().any(|item| match item {
+            SetItem::Literal(literal) => settings.segmentation.normalize(literal) == unit,
+            SetItem::Class(ranges) => {
+                let mut chars = unit.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => ranges.iter().any(|range| range.contains(&c)),
+                    _ => false,
+                }
+            },
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PolicyExpr {
+    Count { range: RangeInclusive<usize>, set: CharSet },
+    Positions { first: usize, second: usize, set: CharSet },
+    Length(RangeInclusive<usize>),
+    Not(Box<PolicyExpr>),
+    And(Box<PolicyExpr>, Box<PolicyExpr>),
+    Or(Box<PolicyExpr>, Box<PolicyExpr>),
+}
+
+impl PolicyExpr {
+    /// Evaluates the policy against a password, counting characters the way
+    /// `settings` asks for. Positions outside the password follow the
+    /// strictness setting like `PositionXor` does.
+    pub fn evaluate(&self, password: &str, settings: &RuleSettings) -> Result<bool, ValidationError> {
+        let password = settings.segmentation.normalize(password);
+        let units = settings.segmentation.units(&password);
+
+        self.evaluate_units(&units, settings)
+    }
+
+    /// The smallest part of the policy that the password fails, or `None`
+    /// if the password satisfies it. For `and` that is the first failing
+    /// operand, otherwise the expression itself.
+    pub fn violation(&self, password: &str, settings: &RuleSettings) -> Result<Option<&PolicyExpr>, ValidationError> {
+        let password = settings.segmentation.normalize(password);
+        let units = settings.segmentation.units(&password);
+
+        self.failing_part(&units, settings)
+    }
+
+    fn failing_part(&self, units: &[&str], settings: &RuleSettings) -> Result<Option<&PolicyExpr>, ValidationError> {
+        match self {
+            PolicyExpr::And(lhs, rhs) => match lhs.failing_part(units, settings)? {
+                Some(part) => Ok(Some(part)),
+                None => rhs.failing_part(units, settings),
+            },
+            _ if self.evaluate_units(units, settings)? => Ok(None),
+            _ => Ok(Some(self)),
+        }
+    }
+
+    fn evaluate_units(&self, units: &[&str], settings: &RuleSettings) -> Result<bool, ValidationError> {
+        match self {
+            PolicyExpr::Count { range, set } => {
+                let count = units.iter()
+                    .filter(|unit| set.matches(unit, settings))
+                    .count();
+                Ok(range.contains(&count))
+            },
+            PolicyExpr::Positions { first, second, set } => {
+                let at = |position: usize| {
+                    match position.checked_sub(1).and_then(|index| units.get(index)) {
+                        Some(unit) => Ok(set.matches(unit, settings)),
+                        None if settings.strictness == Strictness::Lenient => Ok(false),
+                        None => Err(ValidationError::PositionOutOfRange {
+                            position,
+                            length: units.len(),
+                        }),
+                    }
+                };
+                Ok(at(*first)? != at(*second)?)
+            },
+            PolicyExpr::Length(range) => Ok(range.contains(&units.len())),
+            PolicyExpr::Not(expr) => Ok(!expr.evaluate_units(units, settings)?),
+            PolicyExpr::And(lhs, rhs) => {
+                Ok(lhs.evaluate_units(units, settings)? && rhs.evaluate_units(units, settings)?)
+            },
+            PolicyExpr::Or(lhs, rhs) => {
+                Ok(lhs.evaluate_units(units, settings)? || rhs.evaluate_units(units, settings)?)
+            },
+        }
+    }
+}
+
+impl fmt::Display for PolicyExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Parenthesise operands that would otherwise bind differently.
+        let operand = |f: &mut fmt::Formatter, expr: &PolicyExpr, parent_is_and: bool| match expr {
+            PolicyExpr::Or(_, _) if parent_is_and => write!(f, "({})", expr),
+            _ => write!(f, "{}", expr),
+        };
+
+        match self {
+            PolicyExpr::Count { range, set } if *range.end() == usize::MAX => {
+                write!(f, "{}- {}", range.start(), set)
+            },
+            PolicyExpr::Count { range, set } => write!(f, "{}-{} {}", range.start(), range.end(), set),
+            PolicyExpr::Positions { first, second, set } => write!(f, "at {},{} {}", first, second, set),
+            PolicyExpr::Length(range) if range.start() == range.end() => write!(f, "len={}", range.start()),
+            PolicyExpr::Length(range) if *range.end() == usize::MAX => write!(f, "len>={}", range.start()),
+            PolicyExpr::Length(range) => write!(f, "len<={}", range.end()),
+            PolicyExpr::Not(expr) => match **expr {
+                PolicyExpr::And(_, _) | PolicyExpr::Or(_, _) => write!(f, "not ({})", expr),
+                _ => write!(f, "not {}", expr),
+            },
+            PolicyExpr::And(lhs, rhs) => {
+                operand(f, lhs, true)?;
+                write!(f, " and ")?;
+                operand(f, rhs, true)
+            },
+            PolicyExpr::Or(lhs, rhs) => {
+                operand(f, lhs, false)?;
+                write!(f, " or ")?;
+                operand(f, rhs, false)
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PolicyExprError {
+    /// Byte offset into the policy text.
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for PolicyExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.reason, self.position)
+    }
+}
+
+impl FromStr for PolicyExpr {
+    type Err = PolicyExprError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input, position: 0 };
+        let expr = parser.expr()?;
+
+        parser.skip_whitespace();
+        if parser.position < input.len() {
+            return Err(parser.error("Expected 'and', 'or' or end of policy"));
+        }
+
+        Ok(expr)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, reason: &'static str) -> PolicyExprError {
+        PolicyExprError {
+            position: self.position,
+            reason,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes `token` if the input continues with it.
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes a case-insensitive keyword that isn't the prefix of a
+    /// longer word.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let rest = self.rest();
+        let matches = rest.get(..keyword.len())
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+            && !rest[keyword.len()..].starts_with(|c: char| c.is_alphanumeric());
+
+        if matches {
+            self.position += keyword.len();
+        }
+        matches
+    }
+
+    fn expr(&mut self) -> Result<PolicyExpr, PolicyExprError> {
+        let mut expr = self.and()?;
+
+        loop {
+            self.skip_whitespace();
+            if !self.eat_keyword("or") {
+                return Ok(expr);
+            }
+            expr = PolicyExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Result<PolicyExpr, PolicyExprError> {
+        let mut expr = self.unary()?;
+
+        loop {
+            self.skip_whitespace();
+            if !self.eat_keyword("and") {
+                return Ok(expr);
+            }
+            expr = PolicyExpr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<PolicyExpr, PolicyExprError> {
+        self.skip_whitespace();
+
+        if self.eat_keyword("not") {
+            Ok(PolicyExpr::Not(Box::new(self.unary()?)))
+        } else if self.eat("(") {
+            let expr = self.expr()?;
+            self.skip_whitespace();
+            if !self.eat(")") {
+                return Err(self.error("Expected ')'"));
+            }
+            Ok(expr)
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<PolicyExpr, PolicyExprError> {
+        if self.eat_keyword("len") {
+            self.skip_whitespace();
+            let range = if self.eat(">=") {
+                self.skip_whitespace();
+                self.number()?..=usize::MAX
+            } else if self.eat("<=") {
+                self.skip_whitespace();
+                0..=self.number()?
+            } else if self.eat("=") {
+                self.skip_whitespace();
+                let length = self.number()?;
+                length..=length
+            } else {
+                return Err(self.error("Expected '>=', '<=' or '='"));
+            };
+            return Ok(PolicyExpr::Length(range));
+        }
+
+        if self.eat_keyword("at") {
+            self.skip_whitespace();
+            let first = self.number()?;
+            if !self.eat(",") {
+                return Err(self.error("Expected ','"));
+            }
+            self.skip_whitespace();
+            let second = self.number()?;
+            let set = self.set()?;
+            return Ok(PolicyExpr::Positions { first, second, set });
+        }
+
+        let start = self.position;
+        let min = self.number()?;
+        if !self.eat("-") {
+            return Err(self.error("Expected '-'"));
+        }
+        // An open upper bound, as in `1- [0-9]`, means "at least".
+        let max = if self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+            self.number()?
+        } else {
+            usize::MAX
+        };
+        if min > max {
+            return Err(PolicyExprError {
+                position: start,
+                reason: "Min is greater than max",
+            });
+        }
+        let set = self.set()?;
+
+        Ok(PolicyExpr::Count { range: min..=max, set })
+    }
+
+    fn number(&mut self) -> Result<usize, PolicyExprError> {
+        let rest = self.rest();
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+        let number = rest[..digits].parse()
+            .map_err(|_| self.error("Expected a number"))?;
+        self.position += digits;
+        Ok(number)
+    }
+
+    fn set(&mut self) -> Result<CharSet, PolicyExprError> {
+        let mut items = vec![self.set_item()?];
+
+        while self.eat("|") {
+            items.push(self.set_item()?);
+        }
+
+        Ok(CharSet(items))
+    }
+
+    fn set_item(&mut self) -> Result<SetItem, PolicyExprError> {
+        self.skip_whitespace();
+
+        if !self.eat("[") {
+            let grapheme = self.rest().graphemes(true)
+                .next()
+                .filter(|g| !g.trim().is_empty() && !["(", ")", "|"].contains(g))
+                .ok_or_else(|| self.error("Expected a character or a class"))?;
+            self.position += grapheme.len();
+            return Ok(SetItem::Literal(grapheme.to_string()));
+        }
+
+        let mut ranges = vec![];
+        loop {
+            let mut chars = self.rest().chars();
+            let start = match chars.next() {
+                Some(']') if !ranges.is_empty() => {
+                    self.position += 1;
+                    return Ok(SetItem::Class(ranges));
+                },
+                Some(c) => c,
+                None => return Err(self.error("Unterminated class")),
+            };
+
+            let end = match (chars.next(), chars.next()) {
+                (Some('-'), Some(end)) if end != ']' => {
+                    self.position += start.len_utf8() + 1 + end.len_utf8();
+                    end
+                },
+                _ => {
+                    self.position += start.len_utf8();
+                    start
+                },
+            };
+            if start > end {
+                return Err(self.error("Class range is reversed"));
+            }
+            ranges.push(start..=end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Segmentation;
+
+    fn evaluate(policy: &str, password: &str) -> bool {
+        let expr: PolicyExpr = policy.parse().unwrap();
+        expr.evaluate(password, &RuleSettings::default()).unwrap()
+    }
+
+    #[test]
+    fn test_parsing_policies() {
+        assert_eq!(
+            "1-3 a|[0-9]".parse(),
+            Ok(PolicyExpr::Count {
+                range: 1..=3,
+                set: CharSet(vec![
+                    SetItem::Literal("a".to_string()),
+                    SetItem::Class(vec!['0'..='9']),
+                ]),
+            }),
+        );
+        assert_eq!(
+            "len>=8 AND not at 1,3 [a-z_]".parse(),
+            Ok(PolicyExpr::And(
+                Box::new(PolicyExpr::Length(8..=usize::MAX)),
+                Box::new(PolicyExpr::Not(Box::new(PolicyExpr::Positions {
+                    first: 1,
+                    second: 3,
+                    set: CharSet(vec![SetItem::Class(vec!['a'..='z', '_'..='_'])]),
+                }))),
+            )),
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |policy: &str| policy.parse::<PolicyExpr>().unwrap_err();
+
+        assert_eq!(error("1-3"), PolicyExprError { position: 3, reason: "Expected a character or a class" });
+        assert_eq!(error("3-1 a"), PolicyExprError { position: 0, reason: "Min is greater than max" });
+        assert_eq!(error("1-3 ab"), PolicyExprError { position: 5, reason: "Expected 'and', 'or' or end of policy" });
+        assert_eq!(error("(len=3"), PolicyExprError { position: 6, reason: "Expected ')'" });
+        assert_eq!(error("len>3"), PolicyExprError { position: 3, reason: "Expected '>=', '<=' or '='" });
+        assert_eq!(error("1-3 [a-"), PolicyExprError { position: 7, reason: "Unterminated class" });
+        assert_eq!(error("1-3 [z-a]"), PolicyExprError { position: 8, reason: "Class range is reversed" });
+        assert_eq!(error("android"), PolicyExprError { position: 0, reason: "Expected a number" });
+    }
+
+    #[test]
+    fn test_evaluating_policies() {
+        assert!(evaluate("1-3 a", "abcde"));
+        assert!(evaluate("1-3 a|b", "bbb"));
+        assert!(!evaluate("1-3 a|b", "abab"));
+        assert!(evaluate("2- [0-9]", "hunter22"));
+        assert!(!evaluate("2- [0-9]", "hunter2"));
+        assert!(evaluate("1-1 [-]", "a-b"));
+        assert!(evaluate("at 1,3 a", "abcde"));
+        assert!(!evaluate("at 2,9 c", "ccccccccc"));
+        assert!(evaluate("len>=8 and 1- [A-Z] and 1- [0-9]", "Hunter2024"));
+        assert!(!evaluate("len>=8 and 1- [A-Z] and 1- [0-9]", "Hunter2"));
+        assert!(evaluate("len<=4 or (1- [!-/] and not 1- [ ])", "correct-horse"));
+        assert!(!evaluate("len<=4 or (1- [!-/] and not 1- [ ])", "correct horse!"));
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for policy in &[
+            "1-3 a|b",
+            "2- [0-9A-Z_]",
+            "at 1,3 a",
+            "len>=8 and not (1-2 x or len<=3) and len=9",
+            "not at 2,4 [!-/]|é",
+        ] {
+            let expr: PolicyExpr = policy.parse().unwrap();
+            assert_eq!(&expr.to_string(), policy);
+            assert_eq!(expr.to_string().parse(), Ok(expr));
+        }
+    }
+
+    #[test]
+    fn test_violations() {
+        let expr: PolicyExpr = "len>=8 and 1- [0-9] and (1- [A-Z] or 1- [!-/])".parse().unwrap();
+        let violation = |password| expr.violation(password, &RuleSettings::default())
+            .unwrap()
+            .map(|part| part.to_string());
+
+        assert_eq!(violation("Hunter2024"), None);
+        assert_eq!(violation("hunter2"), Some("len>=8".to_string()));
+        assert_eq!(violation("hunter-two"), Some("1- [0-9]".to_string()));
+        assert_eq!(violation("hunter2024"), Some("1- [A-Z] or 1- [!-/]".to_string()));
+    }
+
+    #[test]
+    fn test_evaluating_with_settings() {
+        let expr: PolicyExpr = "at 1,4 a".parse().unwrap();
+        let strict = RuleSettings { strictness: Strictness::Strict, ..RuleSettings::default() };
+        assert_eq!(
+            expr.evaluate("abc", &strict),
+            Err(ValidationError::PositionOutOfRange { position: 4, length: 3 }),
+        );
+
+        let expr: PolicyExpr = "len=2 and 1-1 e\u{301}".parse().unwrap();
+        let graphemes = RuleSettings { segmentation: Segmentation::Graphemes, ..RuleSettings::default() };
+        assert_eq!(expr.evaluate("xe\u{301}", &RuleSettings::default()), Ok(false));
+        assert_eq!(expr.evaluate("xe\u{301}", &graphemes), Ok(true));
+    }
+}
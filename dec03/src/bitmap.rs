@@ -0,0 +1,188 @@
+use crate::{is_a_tree, Map};
+
+/// Bits per word of a row.
+const WORD_BITS: usize = 64;
+
+/// The trees of a map packed one bit per square, each row padded to whole
+/// 64 bit words. Squares that aren't trees, including other terrain, are
+/// clear bits.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TreeBitmap {
+    words: Vec<u64>,
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+}
+
+impl TreeBitmap {
+    pub fn new(map: &Map) -> Self {
+        let (width, height) = (map.grid.width(), map.grid.height());
+        let words_per_row = width.div_ceil(WORD_BITS);
+        let mut words = vec![0; words_per_row * height];
+
+        for ((x, y), square) in map.grid.iter() {
+            if is_a_tree(square) {
+                words[y * words_per_row + x / WORD_BITS] |= 1 << (x % WORD_BITS);
+            }
+        }
+
+        TreeBitmap {
+            words,
+            width,
+            height,
+            words_per_row,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn row(&self, y: usize) -> &[u64] {
+        &self.words[y * self.words_per_row..(y + 1) * self.words_per_row]
+    }
+
+    /// Whether `(x, y)` is a tree. Squares outside the map aren't.
+    pub fn is_tree(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.row(y)[x / WORD_BITS] & (1 << (x % WORD_BITS)) != 0
+    }
+
+    /// Like `is_tree`, with the map repeating in every direction.
+    // For lookups along unreduced paths; `count_trees` keeps its own columns
+    // within the width
+    #[allow(dead_code)]
+    pub fn is_tree_wrapping(&self, x: usize, y: usize) -> bool {
+        self.width != 0 && self.height != 0 && self.is_tree(x % self.width, y % self.height)
+    }
+
+    /// The number of trees in row `y`, a word at a time.
+    pub fn trees_in_row(&self, y: usize) -> usize {
+        if y >= self.height {
+            return 0;
+        }
+        self.row(y).iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Counts the trees hit by each `(down, right)` slope, like
+    /// `traverse_map` does for one, but visiting every row once for all of
+    /// them.
+    pub fn count_trees(&self, slopes: &[(usize, usize)]) -> Vec<usize> {
+        let mut counts = vec![0; slopes.len()];
+        if self.width == 0 {
+            return counts;
+        }
+
+        // Per slope the step to the right within one width, where it is and
+        // the next row it lands on. Slopes not moving down never land.
+        let steps: Vec<usize> = slopes.iter().map(|&(_, right)| right % self.width).collect();
+        let mut xs = vec![0; slopes.len()];
+        let mut next_rows: Vec<usize> = slopes.iter()
+            .map(|&(down, _)| if down == 0 { usize::MAX } else { down })
+            .collect();
+
+        for y in 1..self.height {
+            for (i, &(down, _)) in slopes.iter().enumerate() {
+                if next_rows[i] != y {
+                    continue;
+                }
+                next_rows[i] += down;
+
+                let mut x = xs[i] + steps[i];
+                if x >= self.width {
+                    x -= self.width;
+                }
+                xs[i] = x;
+                if self.is_tree(x, y) {
+                    counts[i] += 1;
+                }
+            }
+        }
+
+        counts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generate::{random_map, Rng};
+    use crate::traverse_map;
+    use std::time::Instant;
+
+    fn forest(rng: &mut Rng, width: usize, height: usize) -> Map {
+        random_map(rng, width, height, 0.25).unwrap()
+    }
+
+    fn traverse_trees(map: &Map, slope: (usize, usize)) -> usize {
+        traverse_map(map, slope).unwrap().iter().filter(|v| is_a_tree(v)).count()
+    }
+
+    #[test]
+    fn test_lookups() {
+        let mut rng = Rng::new(0x5eed);
+        for &(width, height) in &[(1, 1), (11, 11), (64, 3), (65, 4), (200, 7)] {
+            let map = forest(&mut rng, width, height);
+            let bitmap = TreeBitmap::new(&map);
+
+            assert_eq!((bitmap.width(), bitmap.height()), (width, height));
+            for ((x, y), square) in map.grid.iter() {
+                assert_eq!(bitmap.is_tree(x, y), is_a_tree(square), "({}, {})", x, y);
+                assert_eq!(bitmap.is_tree_wrapping(x + 3 * width, y + height), is_a_tree(square));
+            }
+            assert!(!bitmap.is_tree(width, 0));
+            assert!(!bitmap.is_tree(0, height));
+            for (y, row) in map.grid.rows().enumerate() {
+                assert_eq!(bitmap.trees_in_row(y), row.iter().filter(|v| is_a_tree(v)).count());
+            }
+        }
+
+        let empty = TreeBitmap::new(&"".parse().unwrap());
+        assert!(!empty.is_tree_wrapping(3, 3));
+        assert_eq!(empty.count_trees(&[(1, 3)]), vec![0]);
+    }
+
+    #[test]
+    fn test_counting_trees_in_one_pass() {
+        let mut rng = Rng::new(0xf0e57);
+        let slopes: Vec<(usize, usize)> = (0..=4)
+            .flat_map(|down| (0..=150).step_by(7).map(move |right| (down, right)))
+            .collect();
+
+        for &(width, height) in &[(11, 11), (31, 323), (64, 64), (129, 100)] {
+            let map = forest(&mut rng, width, height);
+            let expected: Vec<usize> = slopes.iter().map(|&slope| traverse_trees(&map, slope)).collect();
+            assert_eq!(TreeBitmap::new(&map).count_trees(&slopes), expected, "{}x{}", width, height);
+        }
+    }
+
+    /// Compares the batch count with `traverse_map` on a large forest. Run
+    /// with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_bitmap_against_traverse_map() {
+        let map = forest(&mut Rng::new(0xbe9c4), 31, 2_000_000);
+        let slopes = [(1, 1), (1, 3), (1, 5), (1, 7), (2, 1)];
+
+        let start = Instant::now();
+        let expected: Vec<usize> = slopes.iter().map(|&slope| traverse_trees(&map, slope)).collect();
+        let traversing = start.elapsed();
+
+        let start = Instant::now();
+        let bitmap = TreeBitmap::new(&map);
+        let packing = start.elapsed();
+
+        let start = Instant::now();
+        let counts = bitmap.count_trees(&slopes);
+        let counting = start.elapsed();
+
+        assert_eq!(counts, expected);
+        println!(
+            "traverse_map: {:?}, bitmap: {:?} (+ {:?} to pack)",
+            traversing, counting, packing,
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::grid::Grid;
+use crate::{Map, Slope, Topology};
+
+/// A xorshift generator. Not fit for anything but making up maps, but the
+/// same seed always gives the same maps.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Spreads the seed over the state with a splitmix64 step, so that
+    /// similar seeds give unrelated maps.
+    pub fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        // An all zero state would stay zero forever
+        Rng(if z == 0 { 1 } else { z })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`, `n` must not be zero.
+    #[cfg(test)]
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A map of open squares and trees, where each square is a tree with the
+/// probability `density`.
+pub fn random_map(rng: &mut Rng, width: usize, height: usize, density: f64) -> Result<Map, &'static str> {
+    let squares = width.checked_mul(height).ok_or("The map is too large")?;
+    let cells = (0..squares)
+        .map(|_| if rng.next_f64() < density { Slope::Tree } else { Slope::OpenSquare })
+        .collect();
+
+    Ok(Map {
+        grid: Grid::new(width, height, cells).expect("Cells are generated for every square"),
+        topology: Topology::default(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::is_a_tree;
+
+    #[test]
+    fn test_random_maps() {
+        let map = random_map(&mut Rng::new(7), 30, 20, 0.25).unwrap();
+        assert_eq!((map.grid.width(), map.grid.height()), (30, 20));
+        assert_eq!(map, random_map(&mut Rng::new(7), 30, 20, 0.25).unwrap());
+        assert_ne!(map, random_map(&mut Rng::new(8), 30, 20, 0.25).unwrap());
+
+        // Roughly a quarter are trees
+        let trees = map.grid.iter().filter(|(_, square)| is_a_tree(square)).count();
+        assert!((100..200).contains(&trees), "{} trees", trees);
+
+        let empty = random_map(&mut Rng::new(7), 10, 10, 0.0).unwrap();
+        assert!(empty.grid.iter().all(|(_, square)| !is_a_tree(square)));
+        let full = random_map(&mut Rng::new(7), 10, 10, 1.0).unwrap();
+        assert!(full.grid.iter().all(|(_, square)| is_a_tree(square)));
+
+        let mut rng = Rng::new(0);
+        assert!((0..1000).all(|_| rng.below(3) < 3 && rng.next_f64() < 1.0));
+
+        assert_eq!(random_map(&mut Rng::new(7), usize::MAX, 2, 0.25), Err("The map is too large"));
+    }
+
+    #[test]
+    fn test_seeds() {
+        // Every seed starts a generator that doesn't get stuck, including the
+        // one that used to give an all zero state
+        for &seed in &[0, 1, u64::MAX, 0x9e37_79b9_7f4a_7c15] {
+            let mut rng = Rng::new(seed);
+            assert!((0..100).any(|_| rng.next_f64() > 0.0), "seed {}", seed);
+
+            let map = random_map(&mut Rng::new(seed), 8, 3, 0.25).unwrap();
+            assert!(!map.grid.iter().all(|(_, square)| is_a_tree(square)), "seed {}", seed);
+        }
+        assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::ops::Index;
+
+/// A rectangular grid of cells stored row by row, addressed as `(x, y)` with
+/// `(0, 0)` in the top left corner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T> Grid<T> {
+    /// Creates a grid from its cells in row order.
+    pub fn new(width: usize, height: usize, cells: Vec<T>) -> Result<Self, &'static str> {
+        if width.checked_mul(height) != Some(cells.len()) {
+            return Err("Cell count doesn't match the dimensions");
+        }
+
+        Ok(Grid {
+            cells,
+            width,
+            height,
+        })
+    }
+
+    /// Creates a grid from rows, which must all be equally long.
+    pub fn from_rows<I: IntoIterator<Item = Vec<T>>>(rows: I) -> Result<Self, &'static str> {
+        let mut cells = vec![];
+        let mut width = None;
+        let mut height = 0;
+
+        for row in rows {
+            if *width.get_or_insert(row.len()) != row.len() {
+                return Err("Rows differ in length");
+            }
+            cells.extend(row);
+            height += 1;
+        }
+
+        Ok(Grid {
+            cells,
+            width: width.unwrap_or(0),
+            height,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        if x < self.width && y < self.height {
+            self.cells.get(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Like `get`, but coordinates past an edge continue from the opposite
+    /// edge, as if the grid repeated in every direction.
+    pub fn get_wrapping(&self, x: usize, y: usize) -> Option<&T> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+
+        self.get(x % self.width, y % self.height)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(move |y| &self.cells[y * self.width..(y + 1) * self.width])
+    }
+
+    /// Every cell with its coordinates, in row order.
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let width = self.width;
+        self.cells.iter()
+            .enumerate()
+            .map(move |(i, cell)| ((i % width, i / width), cell))
+    }
+}
+
+// Accessors for later grid puzzles that this one has no use for yet
+#[allow(dead_code)]
+impl<T> Grid<T> {
+    pub fn row(&self, y: usize) -> Option<&[T]> {
+        if y < self.height {
+            Some(&self.cells[y * self.width..(y + 1) * self.width])
+        } else {
+            None
+        }
+    }
+
+    pub fn column(&self, x: usize) -> impl Iterator<Item = &T> {
+        let cells = if x < self.width { &self.cells[x..] } else { &[] };
+        cells.iter().step_by(self.width.max(1))
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |x| self.column(x))
+    }
+
+    /// The up to eight cells around `(x, y)` that lie within the grid, in row
+    /// order, with their coordinates.
+    pub fn neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = ((usize, usize), &T)> {
+        let xs = x.saturating_sub(1)..=x.saturating_add(1);
+        let ys = y.saturating_sub(1)..=y.saturating_add(1);
+
+        ys.flat_map(move |ny| xs.clone().map(move |nx| (nx, ny)))
+            .filter(move |&position| position != (x, y))
+            .filter_map(move |(nx, ny)| self.get(nx, ny).map(|cell| ((nx, ny), cell)))
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        self.get(x, y).expect("Position outside of the grid")
+    }
+}
+
+/// Renders one line per row, with each cell's own `Display` output.
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (y, row) in self.rows().enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn numbers() -> Grid<u8> {
+        Grid::from_rows(vec![
+            vec![1, 2, 3],
+            vec![4, 5, 6],
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_creating_grids() {
+        assert_eq!(Grid::new(3, 2, vec![1, 2, 3, 4, 5, 6]), Ok(numbers()));
+        assert_eq!(Grid::new(3, 2, vec![1, 2, 3]), Err("Cell count doesn't match the dimensions"));
+        assert_eq!(Grid::from_rows(vec![vec![1, 2], vec![3]]), Err("Rows differ in length"));
+
+        let empty: Grid<u8> = Grid::from_rows(vec![]).unwrap();
+        assert_eq!((empty.width(), empty.height()), (0, 0));
+        assert_eq!(empty.get_wrapping(3, 3), None);
+    }
+
+    #[test]
+    fn test_lookups() {
+        let grid = numbers();
+
+        assert_eq!((grid.width(), grid.height()), (3, 2));
+        assert_eq!(grid.get(2, 1), Some(&6));
+        assert_eq!(grid.get(3, 0), None);
+        assert_eq!(grid.get(0, 2), None);
+        assert_eq!(grid.get_wrapping(3, 0), Some(&1));
+        assert_eq!(grid.get_wrapping(7, 3), Some(&5));
+        assert_eq!(grid[(1, 0)], 2);
+    }
+
+    #[test]
+    fn test_iterators() {
+        let grid = numbers();
+
+        assert_eq!(grid.rows().collect::<Vec<&[u8]>>(), vec![&[1, 2, 3][..], &[4, 5, 6][..]]);
+        assert_eq!(grid.row(1), Some(&[4, 5, 6][..]));
+        assert_eq!(grid.row(2), None);
+        assert_eq!(
+            grid.columns().map(|column| column.copied().collect()).collect::<Vec<Vec<u8>>>(),
+            vec![vec![1, 4], vec![2, 5], vec![3, 6]],
+        );
+        assert_eq!(grid.column(3).count(), 0);
+        assert_eq!(grid.iter().nth(4), Some(((1, 1), &5)));
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let grid = numbers();
+
+        assert_eq!(
+            grid.neighbours(0, 0).collect::<Vec<_>>(),
+            vec![((1, 0), &2), ((0, 1), &4), ((1, 1), &5)],
+        );
+        assert_eq!(
+            grid.neighbours(1, 1).map(|(_, cell)| *cell).collect::<Vec<u8>>(),
+            vec![1, 2, 3, 4, 6],
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(numbers().to_string(), "123\n456");
+    }
+}
@@ -0,0 +1,83 @@
+use std::convert::TryFrom;
+
+use crate::Slope;
+
+/// Which symbols a map may contain and what landing on each of them costs.
+/// Open squares (`.`) and trees (`#`) are always known; any other symbol
+/// becomes `Slope::Terrain` once it has a cost.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Legend {
+    costs: Vec<(char, u64)>,
+}
+
+impl Default for Legend {
+    fn default() -> Self {
+        Legend {
+            costs: vec![('.', 0), ('#', 1)],
+        }
+    }
+}
+
+impl Legend {
+    /// Adds a `SYMBOL=COST` entry, replacing the cost of a known symbol.
+    pub fn add(&mut self, entry: &str) -> Result<(), &'static str> {
+        let mut chars = entry.chars();
+        let symbol = chars.next().ok_or("Tiles are written SYMBOL=COST")?;
+        let cost = chars.as_str()
+            .strip_prefix('=')
+            .ok_or("Tiles are written SYMBOL=COST")?
+            .parse()
+            .map_err(|_| "Invalid tile cost")?;
+        if symbol.is_whitespace() {
+            return Err("Tile symbols can't be whitespace");
+        }
+
+        match self.costs.iter_mut().find(|(known, _)| *known == symbol) {
+            Some(known) => known.1 = cost,
+            None => self.costs.push((symbol, cost)),
+        }
+        Ok(())
+    }
+
+    pub fn tile(&self, symbol: char) -> Option<Slope> {
+        if !self.costs.iter().any(|&(known, _)| known == symbol) {
+            return None;
+        }
+
+        Some(Slope::try_from(symbol).unwrap_or(Slope::Terrain(symbol)))
+    }
+
+    pub fn cost(&self, square: &Slope) -> u64 {
+        let symbol = square.symbol();
+        self.costs.iter()
+            .find(|&&(known, _)| known == symbol)
+            .map_or(0, |&(_, cost)| cost)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_legend() {
+        let mut legend = Legend::default();
+        assert_eq!(legend.tile('#'), Some(Slope::Tree));
+        assert_eq!(legend.tile('^'), None);
+        assert_eq!(legend.cost(&Slope::Tree), 1);
+
+        legend.add("^=5").unwrap();
+        legend.add("#=3").unwrap();
+        legend.add("==2").unwrap();
+        assert_eq!(legend.tile('^'), Some(Slope::Terrain('^')));
+        assert_eq!(legend.tile('='), Some(Slope::Terrain('=')));
+        assert_eq!(legend.cost(&Slope::Terrain('^')), 5);
+        assert_eq!(legend.cost(&Slope::Tree), 3);
+        assert_eq!(legend.cost(&Slope::OpenSquare), 0);
+
+        assert_eq!(legend.add(""), Err("Tiles are written SYMBOL=COST"));
+        assert_eq!(legend.add("~5"), Err("Tiles are written SYMBOL=COST"));
+        assert_eq!(legend.add("~=-1"), Err("Invalid tile cost"));
+        assert_eq!(legend.add(" =1"), Err("Tile symbols can't be whitespace"));
+    }
+}
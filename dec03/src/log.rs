@@ -0,0 +1,89 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// How much is logged to stderr, raised by each `-v`.
+#[derive(Debug, Default, PartialEq, PartialOrd, Copy, Clone)]
+pub enum Verbosity {
+    /// Nothing but errors
+    #[default]
+    Quiet,
+    /// The map and a summary per slope
+    Info,
+    /// Every step of every traversal
+    Steps,
+}
+
+impl Verbosity {
+    pub fn raise(self) -> Self {
+        match self {
+            Verbosity::Quiet => Verbosity::Info,
+            Verbosity::Info | Verbosity::Steps => Verbosity::Steps,
+        }
+    }
+}
+
+/// Where traversal messages go. Messages reach stderr depending on the
+/// verbosity, while a trace file gets every step regardless of it.
+pub struct Log {
+    verbosity: Verbosity,
+    trace: Option<Box<dyn Write>>,
+    trace_error: Option<io::Error>,
+}
+
+impl Log {
+    pub fn new(verbosity: Verbosity, trace: Option<Box<dyn Write>>) -> Self {
+        Log {
+            verbosity,
+            trace,
+            trace_error: None,
+        }
+    }
+
+    /// A log that drops everything.
+    pub fn quiet() -> Self {
+        Log::new(Verbosity::Quiet, None)
+    }
+
+    /// A log that also writes the step log to the file at `path`.
+    pub fn with_trace_file(verbosity: Verbosity, path: &str) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Log::new(verbosity, Some(Box::new(BufWriter::new(file)))))
+    }
+
+    pub fn info(&self, message: fmt::Arguments) {
+        if self.verbosity >= Verbosity::Info {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Whether `step` goes anywhere, so callers can skip building messages.
+    pub fn traces_steps(&self) -> bool {
+        self.verbosity >= Verbosity::Steps || self.trace.is_some()
+    }
+
+    pub fn step(&mut self, message: fmt::Arguments) {
+        if self.verbosity >= Verbosity::Steps {
+            eprintln!("{}", message);
+        }
+
+        // Only the first failure is kept, later steps aren't written at all
+        if let Some(trace) = &mut self.trace {
+            if let Err(err) = writeln!(trace, "{}", message) {
+                self.trace_error = Some(err);
+                self.trace = None;
+            }
+        }
+    }
+
+    /// Flushes the trace file and reports whether writing it failed.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.trace_error.take() {
+            return Err(err);
+        }
+        match &mut self.trace {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+}
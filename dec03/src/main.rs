@@ -1,48 +1,519 @@
-use std::io::{self, BufRead};
+mod bitmap;
+mod generate;
+mod grid;
+mod legend;
+mod log;
+mod render;
+mod route;
+
+use std::io::{self, Read, Write};
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::process;
+
+use bitmap::TreeBitmap;
+use generate::{random_map, Rng};
+use grid::Grid;
+use legend::Legend;
+use log::{Log, Verbosity};
+use render::{Overlay, RenderFormat};
+use route::{cheapest_route, Move};
+
+const USAGE: &str = "usage: dec03 [--slope RIGHT,DOWN]... [--part1] \
+                     [--search [--max-right N] [--max-down N]] \
+                     [--route [--move down|down-right|down-left]...] \
+                     [--render ansi|svg|ppm] [--bitmap] [--tile SYMBOL=COST]... \
+                     [--topology horizontal|torus|bounded|bounded-strict] \
+                     [-v|-vv] [--trace FILE] < map\n       \
+                     dec03 --generate WIDTH,HEIGHT [--density D] [--seed N]";
 
 fn main() {
-    let stdin = io::stdin();
+    let options = match Options::from_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
 
-    let mut lines: usize = 0;
-    let mut items: Vec<Slope> = vec![];
+    if let Some(generator) = options.generate {
+        let mut rng = Rng::new(generator.seed);
+        match random_map(&mut rng, generator.width, generator.height, generator.density) {
+            Ok(map) => println!("{}", map),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
 
-    for line in stdin.lock().lines() {
-        lines += 1;
-        for val in line.unwrap().chars() {
-            items.push(Slope::try_from(val).unwrap());
+    let mut input = String::new();
+    if io::stdin().read_to_string(&mut input).is_err() {
+        eprintln!("Unable to read the map");
+        process::exit(1);
+    }
+
+    let mut map = match Map::parse(&input, &options.legend) {
+        Ok(map) => map,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
         }
+    };
+    map.topology = options.topology;
+
+    let mut log = match &options.trace {
+        Some(path) => match Log::with_trace_file(options.verbosity, path) {
+            Ok(log) => log,
+            Err(err) => {
+                eprintln!("Unable to create the trace file {}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => Log::new(options.verbosity, None),
+    };
+    log.info(format_args!("map: {} wide, {} high", map.grid.width(), map.grid.height()));
+
+    if let Some(moves) = &options.route {
+        print_route(&map, &options.legend, moves, options.render, &log);
+    } else {
+        print_slope_modes(&map, &options, &mut log);
+    }
+
+    if let Err(err) = log.finish() {
+        eprintln!("Unable to write the trace file: {}", err);
+        process::exit(1);
     }
-    let pattern_width = items.len() / lines as usize;
-    let map = Map {
-        items,
-        pattern_height: lines,
-        pattern_width,
+}
+
+fn print_slope_modes(map: &Map, options: &Options, log: &mut Log) {
+    match (options.search, options.render) {
+        (Some(bounds), _) => print_search(map, &options.legend, bounds, log),
+        (None, Some(format)) => render_slopes(map, &options.slopes, format, log),
+        (None, None) if options.bitmap => print_bitmap_slopes(map, &options.slopes, log),
+        (None, None) => print_slopes(map, &options.legend, &options.slopes, log),
+    }
+}
+
+fn print_route(map: &Map, legend: &Legend, moves: &[Move], render: Option<RenderFormat>, log: &Log) {
+    let route = match cheapest_route(map, legend, moves) {
+        Some(route) => route,
+        None => {
+            eprintln!("No route leads to the bottom of the map");
+            process::exit(1);
+        }
+    };
+    log.info(format_args!("route: cost {}, {} trees", route.cost, route.trees));
+
+    if let Some(format) = render {
+        let output = match Overlay::new(map, &[route.positions]) {
+            Ok(overlay) => overlay.render(format),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        };
+        if io::stdout().write_all(&output).is_err() {
+            eprintln!("Unable to write the rendering");
+            process::exit(1);
+        }
+        return;
+    }
+
+    // row, column, square - one tab separated row per step of the route
+    println!("row\tcolumn\tsquare");
+    for &(x, y) in &route.positions {
+        println!("{}\t{}\t{}", y, x, map.grid[(x, y)]);
+    }
+    println!("trees\t{}", route.trees);
+    println!("cost\t{}", route.cost);
+}
+
+fn render_slopes(map: &Map, slopes: &[(usize, usize)], format: RenderFormat, log: &Log) {
+    let paths: Vec<Vec<(usize, usize)>> = match slopes.iter()
+        .map(|&slope| path_positions(map, slope).map_err(|err| (slope, err)))
+        .collect()
+    {
+        Ok(paths) => paths,
+        Err((slope, err)) => {
+            eprintln!("slope {}: {}", format_slope(slope), err);
+            process::exit(1);
+        }
     };
+    log.info(format_args!("rendering {} paths", paths.len()));
+    let output = match Overlay::new(map, &paths) {
+        Ok(overlay) => overlay.render(format),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    if io::stdout().write_all(&output).is_err() {
+        eprintln!("Unable to write the rendering");
+        process::exit(1);
+    }
+}
+
+fn print_slopes(map: &Map, legend: &Legend, slopes: &[(usize, usize)], log: &mut Log) {
+    let mut product: Option<usize> = Some(1);
+
+    // slope, trees hit, squares visited, total cost - one tab separated row
+    // per slope
+    println!("slope\ttrees\tlength\tcost");
+    for &slope in slopes {
+        let path = match trace_map(map, slope, log) {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("slope {}: {}", format_slope(slope), err);
+                process::exit(1);
+            }
+        };
+        let report = SlopeReport::from_path(&path, legend);
+        let cost = match report.cost {
+            Some(cost) => cost,
+            None => {
+                eprintln!("slope {}: Total cost overflows", format_slope(slope));
+                process::exit(1);
+            }
+        };
+        println!("{}\t{}\t{}\t{}", format_slope(slope), report.trees, report.length, cost);
+        product = product.and_then(|product| product.checked_mul(report.trees));
+    }
+
+    match product {
+        Some(product) => println!("product\t{}", product),
+        None => {
+            eprintln!("Product of the tree counts overflows");
+            process::exit(1);
+        }
+    }
+}
 
-    let slopes: &[(usize, usize)] = &[
-        (1, 1),
-        (1, 3),
-        (1, 5),
-        (1, 7),
-        (2, 1),
-    ];
+/// Like `print_slopes`, with all slopes counted in one pass over a packed
+/// copy of the map. Only trees count, at a cost of one each.
+fn print_bitmap_slopes(map: &Map, slopes: &[(usize, usize)], log: &Log) {
+    let bitmap = TreeBitmap::new(map);
+    log.info(format_args!(
+        "packed {} by {} squares, {} trees",
+        bitmap.width(),
+        bitmap.height(),
+        (0..bitmap.height()).map(|y| bitmap.trees_in_row(y)).sum::<usize>(),
+    ));
+    let counts = bitmap.count_trees(slopes);
 
-    let mut multipled_trees = 1;
+    let mut product: Option<usize> = Some(1);
 
-    for slope in slopes {
-        multipled_trees *= traverse_map(&map, *slope).into_iter()
-        .filter(|v| is_a_tree(v))
-        .count();
+    // slope, trees hit, squares visited, total cost - as `print_slopes`
+    println!("slope\ttrees\tlength\tcost");
+    for (&(down, right), &trees) in slopes.iter().zip(&counts) {
+        let length = bitmap.height().saturating_sub(1).checked_div(down).unwrap_or(0);
+        println!("{}\t{}\t{}\t{}", format_slope((down, right)), trees, length, trees);
+        product = product.and_then(|product| product.checked_mul(trees));
     }
 
-    eprintln!("Number of trees: {}", multipled_trees);
+    match product {
+        Some(product) => println!("product\t{}", product),
+        None => {
+            eprintln!("Product of the tree counts overflows");
+            process::exit(1);
+        }
+    }
+}
+
+fn print_search(map: &Map, legend: &Legend, bounds: SearchBounds, log: &Log) {
+    // Without bounds every distinct step to the right and every step down
+    // that still lands on the map is tried
+    let max_down = bounds.max_down.unwrap_or_else(|| map.grid.height().saturating_sub(1));
+    let max_right = bounds.max_right.unwrap_or_else(|| map.grid.width().saturating_sub(1));
+    let ranking = rank_slopes(map, legend, max_down, max_right);
+    log.info(format_args!("ranked {} slopes", ranking.len()));
+
+    // rank, slope, trees hit, squares visited, total cost - fewest trees first
+    println!("rank\tslope\ttrees\tlength\tcost");
+    for (rank, (slope, report)) in ranking.iter().enumerate() {
+        let cost = match report.cost {
+            Some(cost) => cost,
+            None => {
+                eprintln!("slope {}: Total cost overflows", format_slope(*slope));
+                process::exit(1);
+            }
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            rank + 1, format_slope(*slope), report.trees, report.length, cost,
+        );
+    }
+
+    if let (Some(fewest), Some(most)) = (ranking.first(), most_trees(&ranking)) {
+        println!("fewest\t{}\t{}", format_slope(fewest.0), fewest.1.trees);
+        println!("most\t{}\t{}", format_slope(most.0), most.1.trees);
+    }
+}
+
+/// The slopes from part two of the puzzle, as `(down, right)`.
+const PART_TWO_SLOPES: &[(usize, usize)] = &[
+    (1, 1),
+    (1, 3),
+    (1, 5),
+    (1, 7),
+    (2, 1),
+];
+
+/// The only slope of part one of the puzzle.
+const PART_ONE_SLOPE: (usize, usize) = (1, 3);
+
+/// Command line options. Slopes are stored as `(down, right)` like
+/// `traverse_map` takes them, but written `RIGHT,DOWN` on the command line
+/// like the puzzle describes them.
+#[derive(Debug, PartialEq)]
+struct Options {
+    slopes: Vec<(usize, usize)>,
+    search: Option<SearchBounds>,
+    render: Option<RenderFormat>,
+    verbosity: Verbosity,
+    trace: Option<String>,
+    legend: Legend,
+    bitmap: bool,
+    topology: Topology,
+    /// The moves to find the cheapest route with, instead of following slopes
+    route: Option<Vec<Move>>,
+    /// Print a random map instead of reading one
+    generate: Option<Generator>,
+}
+
+/// The size and tree density of a random map, and the seed to make it with.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Generator {
+    width: usize,
+    height: usize,
+    density: f64,
+    seed: u64,
+}
+
+/// The largest steps tried when searching for the best slope. Missing bounds
+/// default to the map's size.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+struct SearchBounds {
+    max_down: Option<usize>,
+    max_right: Option<usize>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            slopes: PART_TWO_SLOPES.to_vec(),
+            search: None,
+            render: None,
+            verbosity: Verbosity::default(),
+            trace: None,
+            legend: Legend::default(),
+            bitmap: false,
+            topology: Topology::default(),
+            route: None,
+            generate: None,
+        }
+    }
+}
+
+impl Options {
+    fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self, &'static str> {
+        let mut options = Options::default();
+        let mut slopes = vec![];
+        let mut part_one = false;
+        let mut search = false;
+        let mut bounds = SearchBounds::default();
+        let mut route = false;
+        let mut moves = vec![];
+        let mut size = None;
+        let mut density = None;
+        let mut seed = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--slope" | "-s" => {
+                    slopes.push(parse_slope(&args.next().ok_or("Missing value for --slope")?)?);
+                }
+                "--part1" => part_one = true,
+                "--search" => search = true,
+                "--bitmap" => options.bitmap = true,
+                "--route" => route = true,
+                "--generate" => {
+                    let value = args.next().ok_or("Missing value for --generate")?;
+                    let (width, height) = value.split_once(',').ok_or("Sizes are written WIDTH,HEIGHT")?;
+                    size = Some((
+                        width.trim().parse().map_err(|_| "Invalid value for --generate")?,
+                        height.trim().parse().map_err(|_| "Invalid value for --generate")?,
+                    ));
+                }
+                "--density" => {
+                    density = Some(args.next()
+                        .ok_or("Missing value for --density")?
+                        .parse::<f64>()
+                        .map_err(|_| "Invalid value for --density")?);
+                }
+                "--seed" => {
+                    seed = Some(args.next()
+                        .ok_or("Missing value for --seed")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --seed")?);
+                }
+                "--move" => moves.push(args.next().ok_or("Missing value for --move")?.parse()?),
+                "--topology" => {
+                    options.topology = args.next()
+                        .ok_or("Missing value for --topology")?
+                        .parse()?;
+                }
+                "--render" => {
+                    options.render = Some(args.next()
+                        .ok_or("Missing value for --render")?
+                        .parse()?);
+                }
+                "--max-right" => {
+                    bounds.max_right = Some(args.next()
+                        .ok_or("Missing value for --max-right")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --max-right")?);
+                }
+                "--max-down" => {
+                    bounds.max_down = Some(args.next()
+                        .ok_or("Missing value for --max-down")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --max-down")?);
+                }
+                "--tile" => options.legend.add(&args.next().ok_or("Missing value for --tile")?)?,
+                "--verbose" | "-v" => options.verbosity = options.verbosity.raise(),
+                "-vv" => options.verbosity = options.verbosity.raise().raise(),
+                "--trace" => options.trace = Some(args.next().ok_or("Missing value for --trace")?),
+                "--help" | "-h" => return Err("Help requested"),
+                _ => return Err("Unknown argument"),
+            }
+        }
+
+        // Only the slope table follows paths step by step
+        let traces = options.trace.is_some() || options.verbosity == Verbosity::Steps;
+        if traces && (size.is_some() || route || search || options.render.is_some()) {
+            return Err("--trace and -vv can't be combined with --generate, --route, --search or --render");
+        }
+
+        if let Some((width, height)) = size {
+            let density = density.unwrap_or(0.25);
+            if !(0.0..=1.0).contains(&density) {
+                return Err("The density has to be between 0 and 1");
+            }
+            options.generate = Some(Generator {
+                width,
+                height,
+                density,
+                seed: seed.unwrap_or(1),
+            });
+            return Ok(options);
+        } else if density.is_some() || seed.is_some() {
+            return Err("--density and --seed need --generate");
+        }
+
+        if route {
+            if search || part_one || options.bitmap || !slopes.is_empty() {
+                return Err("--route can't be combined with --slope, --part1, --search or --bitmap");
+            }
+            if moves.is_empty() {
+                moves = Move::ALL.to_vec();
+            }
+            options.route = Some(moves);
+            return Ok(options);
+        } else if !moves.is_empty() {
+            return Err("Moves need --route");
+        }
+
+        if options.bitmap {
+            if search || options.render.is_some() {
+                return Err("--bitmap can't be combined with --search or --render");
+            }
+            if options.legend != Legend::default() || options.trace.is_some() || options.verbosity == Verbosity::Steps {
+                return Err("--bitmap only counts trees, without tiles or steps to trace");
+            }
+            if options.topology != Topology::Horizontal {
+                return Err("--bitmap only supports the horizontal topology");
+            }
+        }
+
+        if search {
+            if part_one || !slopes.is_empty() || options.render.is_some() {
+                return Err("--search can't be combined with --slope, --part1 or --render");
+            }
+            options.search = Some(bounds);
+        } else if bounds != SearchBounds::default() {
+            return Err("Search bounds need --search");
+        } else if part_one {
+            if !slopes.is_empty() {
+                return Err("--part1 can't be combined with --slope");
+            }
+            options.slopes = vec![PART_ONE_SLOPE];
+        } else if !slopes.is_empty() {
+            options.slopes = slopes;
+        }
+
+        Ok(options)
+    }
+}
+
+/// Reads a `RIGHT,DOWN` slope into `(down, right)`.
+fn parse_slope(input: &str) -> Result<(usize, usize), &'static str> {
+    let (right, down) = input.split_once(',').ok_or("Slopes are written RIGHT,DOWN")?;
+    let right = right.trim().parse().map_err(|_| "Invalid value for --slope")?;
+    let down: usize = down.trim().parse().map_err(|_| "Invalid value for --slope")?;
+    if down == 0 {
+        return Err("A slope has to move down");
+    }
+
+    Ok((down, right))
+}
+
+fn format_slope((down, right): (usize, usize)) -> String {
+    format!("{},{}", right, down)
+}
+
+/// What sliding down one slope of a map runs into.
+#[derive(Debug, PartialEq)]
+struct SlopeReport {
+    /// Trees hit on the way down
+    trees: usize,
+    /// Squares visited, not counting the start
+    length: usize,
+    /// Sum of the legend's costs of the visited squares, `None` if it
+    /// overflows
+    cost: Option<u64>,
+}
+
+impl SlopeReport {
+    fn from_path(path: &[Slope], legend: &Legend) -> Self {
+        SlopeReport {
+            trees: path.iter().filter(|v| is_a_tree(v)).count(),
+            length: path.len(),
+            cost: path.iter().try_fold(0u64, |cost, square| cost.checked_add(legend.cost(square))),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 enum Slope {
     OpenSquare,
     Tree,
+    /// Any other kind of square, known by its symbol in the `Legend`
+    Terrain(char),
+}
+
+impl Slope {
+    fn symbol(&self) -> char {
+        match self {
+            Slope::OpenSquare => '.',
+            Slope::Tree => '#',
+            Slope::Terrain(symbol) => *symbol,
+        }
+    }
 }
 
 impl TryFrom<char> for Slope {
@@ -57,99 +528,265 @@ impl TryFrom<char> for Slope {
     }
 }
 
+impl fmt::Display for Slope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
 fn is_a_tree(slope: &Slope) -> bool {
     slope == &Slope::Tree
 }
 
+/// One repetition of the pattern. How it continues past its edges depends on
+/// the topology.
 #[derive(Debug, PartialEq)]
 struct Map {
-    items: Vec<Slope>,
-    pattern_width: usize,
-    pattern_height: usize,
+    grid: Grid<Slope>,
+    topology: Topology,
 }
 
-impl std::str::FromStr for Map {
+/// How the map continues past its edges, and so where a path ends.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+enum Topology {
+    /// The pattern repeats to the right, paths end past the bottom
+    #[default]
+    Horizontal,
+    /// The pattern repeats in every direction, paths end back at the start
+    Torus,
+    /// Nothing lies past the edges, paths end past the bottom or right edge
+    Bounded,
+    /// Like `Bounded`, but leaving through the right edge is an error
+    BoundedStrict,
+}
+
+impl std::str::FromStr for Topology {
     type Err = &'static str;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let mut lines: usize = 0;
-        let line = input.lines().next().unwrap().chars();
-        let mut pattern_width: usize = 0;
-        for _ in line {
-            pattern_width = pattern_width + 1;
-        }
-
-        let items: Vec<Slope> = input
-            .lines()
-            .flat_map(|l| {
-                lines = lines + 1;
-                l.chars()
-            })
-            .map(|c| Slope::try_from(c).unwrap())
-            .collect();
+        match input {
+            "horizontal" => Ok(Topology::Horizontal),
+            "torus" => Ok(Topology::Torus),
+            "bounded" => Ok(Topology::Bounded),
+            "bounded-strict" => Ok(Topology::BoundedStrict),
+            _ => Err("Unknown topology"),
+        }
+    }
+}
+
+/// Why a map couldn't be parsed. Rows and columns count from 1.
+#[derive(Debug, PartialEq)]
+enum MapParseError {
+    UnknownSquare { row: usize, column: usize, found: char },
+    RaggedRow { row: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for MapParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapParseError::UnknownSquare { row, column, found } => {
+                write!(f, "row {}, column {}: unknown square {:?}", row, column, found)
+            }
+            MapParseError::RaggedRow { row, expected, found } => {
+                write!(f, "row {}: expected {} squares like the first row, found {}", row, expected, found)
+            }
+        }
+    }
+}
+
+/// Reads a map of open squares and trees only, repeating to the right.
+impl std::str::FromStr for Map {
+    type Err = MapParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Map::parse(input, &Legend::default())
+    }
+}
+
+impl Map {
+    /// Reads one row per line, with `\n` or `\r\n` line endings, taking the
+    /// symbols from the legend. Every row must be as wide as the first one.
+    /// The map repeats to the right until its topology is changed.
+    fn parse(input: &str, legend: &Legend) -> Result<Self, MapParseError> {
+        let mut rows: Vec<Vec<Slope>> = vec![];
+
+        for (i, line) in input.lines().enumerate() {
+            let row = line.chars()
+                .enumerate()
+                .map(|(j, c)| legend.tile(c).ok_or(MapParseError::UnknownSquare {
+                    row: i + 1,
+                    column: j + 1,
+                    found: c,
+                }))
+                .collect::<Result<Vec<Slope>, _>>()?;
+
+            if let Some(first) = rows.first() {
+                if first.len() != row.len() {
+                    return Err(MapParseError::RaggedRow {
+                        row: i + 1,
+                        expected: first.len(),
+                        found: row.len(),
+                    });
+                }
+            }
+            rows.push(row);
+        }
 
         Ok(Map {
-            items,
-            pattern_height: lines,
-            pattern_width,
+            grid: Grid::from_rows(rows).expect("Rows are checked to be equally long"),
+            topology: Topology::default(),
         })
     }
 }
 
-fn traverse_map(map: &Map, movements: (usize, usize)) -> Vec<Slope> {
-    let mut reached_bottom = false;
-    let mut result = vec![];
-    let mut steps = vec![];
-
-    for x in 1..=movements.1 {
-        steps.push((0, 1));
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.grid)
     }
-    for y in 1..=movements.0 {
-        steps.push((map.pattern_width, 0));
+}
+
+/// The `(x, y)` positions a path from the top left corner lands on, moving
+/// `(down, right)` at a time. The start isn't included.
+///
+/// How far it goes depends on the map's topology: with the horizontal one
+/// `x` continues past the right edge, and a slope that never moves down has
+/// no end, so it visits nothing. On a torus positions wrap around and the
+/// path makes one lap, landing back on the start last. On a bounded map the
+/// path also ends at the right edge, which the strict variant reports as an
+/// error.
+fn path_positions(map: &Map, (down, right): (usize, usize)) -> Result<Vec<(usize, usize)>, &'static str> {
+    let (width, height) = (map.grid.width(), map.grid.height());
+    let mut positions = vec![];
+
+    if width == 0 {
+        return Ok(positions);
     }
 
-    //let movements = [
-    //    (0, 1),
-    //    (0, 1),
-    //    (0, 1),
-    //    (map.pattern_width, 0),
-    //];
-    let mut counter = 0;
-    let mut x_pos = 0;
-    let mut y_pos = 0;
+    match map.topology {
+        Topology::Horizontal => {
+            if down == 0 {
+                return Ok(positions);
+            }
 
-    eprintln!("width: {}, height: {}", map.pattern_width, map.pattern_height);
+            let (mut x, mut y): (usize, usize) = (0, down);
+            while y < height {
+                x = x.checked_add(right).ok_or("Path runs too far to the right")?;
+                positions.push((x, y));
+                y += down;
+            }
+        }
+        Topology::Torus => {
+            let (step_down, step_right) = (down % height, right % width);
+            let (mut x, mut y) = (0, 0);
+            loop {
+                x = (x + step_right) % width;
+                y = (y + step_down) % height;
+                positions.push((x, y));
+                if (x, y) == (0, 0) {
+                    break;
+                }
+            }
+        }
+        Topology::Bounded | Topology::BoundedStrict => {
+            if (down, right) == (0, 0) {
+                return Ok(positions);
+            }
 
-    while reached_bottom == false {
-        for movement in &steps {
-            match movement {
-                (0, sideway) => {
-                    if x_pos >= map.pattern_width - 1 {
-                        counter -= map.pattern_width - 1;
-                        x_pos = 0;
-                    } else {
-                        counter += sideway;
-                        x_pos += sideway;
+            let (mut x, mut y) = (right, down);
+            while y < height {
+                if x >= width {
+                    if map.topology == Topology::BoundedStrict {
+                        return Err("Path leaves the right edge of the map");
                     }
-                },
-                (south, 0) => {
-                    counter += south;
-                    y_pos += 1;
+                    break;
+                }
+                positions.push((x, y));
+                x = x.saturating_add(right);
+                y = y.saturating_add(down);
+            }
+        }
+    }
 
-                    if y_pos >= map.pattern_height - 1 {
-                        reached_bottom = true;
-                    }
-                },
-                (_, _) => panic!("Unmatched movement"),
-            };
+    Ok(positions)
+}
+
+/// Slides from the top left corner by `(down, right)` at a time and returns
+/// the square landed on at every step, following `path_positions`.
+fn traverse_map(map: &Map, slope: (usize, usize)) -> Result<Vec<Slope>, &'static str> {
+    trace_map(map, slope, &mut Log::quiet())
+}
+
+/// Like `traverse_map`, logging every step and a summary of the slope.
+fn trace_map(map: &Map, (down, right): (usize, usize), log: &mut Log) -> Result<Vec<Slope>, &'static str> {
+    let slope = format_slope((down, right));
+    let positions = path_positions(map, (down, right)).inspect_err(|err| {
+        log.info(format_args!("slope {}: {}", slope, err));
+    })?;
+
+    let mut result = Vec::with_capacity(positions.len());
+    for (x, y) in positions {
+        let square = *map.grid.get_wrapping(x, y).expect("Paths stay on the map");
+
+        if log.traces_steps() {
+            // slope, x (not wrapped), y, square
+            log.step(format_args!("{}\t{}\t{}\t{}", slope, x, y, square));
         }
 
-        eprintln!("{}, xy: {}, {}, {}", counter, x_pos, y_pos, is_a_tree(&map.items[counter]));
+        result.push(square);
+    }
 
-        result.push(map.items[counter]);
+    log.info(format_args!(
+        "slope {}: {} squares, {} trees",
+        slope,
+        result.len(),
+        result.iter().filter(|v| is_a_tree(v)).count(),
+    ));
+    Ok(result)
+}
+
+/// Tries every slope moving `1..=max_down` down and `0..=max_right` right,
+/// and ranks them by the number of trees hit, fewest first. Ties are ordered
+/// by the smaller step down, then the smaller step right.
+///
+/// When the map repeats to the right, slopes on the same line share work:
+/// `(k * down, k * right)` lands on every `k`th square of `(down, right)`,
+/// so each line is only traversed once, for its smallest slope. Slopes that
+/// can't be traversed on the map's topology are left out.
+fn rank_slopes(
+    map: &Map,
+    legend: &Legend,
+    max_down: usize,
+    max_right: usize,
+) -> Vec<((usize, usize), SlopeReport)> {
+    let mut lines: HashMap<(usize, usize), Option<Vec<Slope>>> = HashMap::new();
+    let mut ranking = vec![];
+
+    for down in 1..=max_down {
+        for right in 0..=max_right {
+            let k = if map.topology == Topology::Horizontal { gcd(down, right) } else { 1 };
+            let line = (down / k, right / k);
+            let path = match lines.entry(line).or_insert_with(|| traverse_map(map, line).ok()) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let visited: Vec<Slope> = path.iter().skip(k - 1).step_by(k).copied().collect();
+            ranking.push(((down, right), SlopeReport::from_path(&visited, legend)));
+        }
     }
 
-    result
+    ranking.sort_by_key(|&(slope, ref report)| (report.trees, slope));
+    ranking
+}
+
+/// The first of the slopes hitting the most trees in a ranking.
+fn most_trees(ranking: &[((usize, usize), SlopeReport)]) -> Option<&((usize, usize), SlopeReport)> {
+    let most = ranking.last()?.1.trees;
+    ranking.iter().find(|(_, report)| report.trees == most)
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
 }
 
 #[cfg(test)]
@@ -200,24 +837,452 @@ mod test {
         assert_eq!(Slope::try_from('d'), Err("Unknown slope"));
 
         assert_eq!("..#\r\n##.".parse(), Ok(Map {
-            items: vec![
+            grid: Grid::new(3, 2, vec![
                 Slope::OpenSquare,
                 Slope::OpenSquare,
                 Slope::Tree,
                 Slope::Tree,
                 Slope::Tree,
                 Slope::OpenSquare,
-            ],
-            pattern_width: 3,
-            pattern_height: 2,
+            ]).unwrap(),
+            topology: Topology::Horizontal,
         }));
     }
 
+    #[test]
+    fn test_rejecting_malformed_maps() {
+        assert_eq!(
+            "..#\n.#.#\n...".parse::<Map>(),
+            Err(MapParseError::RaggedRow { row: 2, expected: 3, found: 4 }),
+        );
+        assert_eq!(
+            "..#\r\n#.\r\n".parse::<Map>(),
+            Err(MapParseError::RaggedRow { row: 2, expected: 3, found: 2 }),
+        );
+        assert_eq!(
+            "..#\n.#.\n.O.".parse::<Map>(),
+            Err(MapParseError::UnknownSquare { row: 3, column: 2, found: 'O' }),
+        );
+        assert_eq!(
+            MapParseError::UnknownSquare { row: 3, column: 2, found: 'O' }.to_string(),
+            "row 3, column 2: unknown square 'O'",
+        );
+
+        // A trailing line ending doesn't add an empty row
+        assert_eq!("..#\r\n##.\r\n".parse::<Map>(), "..#\n##.".parse::<Map>());
+    }
+
+    #[test]
+    fn test_rendering_map() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        assert_eq!(map.to_string(), TEST_INPUT.replace("\r\n", "\n"));
+    }
+
+    /// Walks the map the slow and obvious way: repeat every row until it's
+    /// wide enough for the whole path and step through it literally.
+    fn naive_traverse(input: &str, (down, right): (usize, usize)) -> Vec<Slope> {
+        let rows: Vec<&str> = input.lines().collect();
+        let mut result = vec![];
+        if down == 0 {
+            return result;
+        }
+
+        let steps = rows.len() / down + 1;
+        let (mut x, mut y) = (0, 0);
+        loop {
+            x += right;
+            y += down;
+            if y >= rows.len() {
+                return result;
+            }
+            let repeated = rows[y].repeat(steps * right / rows[y].len() + 1);
+            result.push(Slope::try_from(repeated.chars().nth(x).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_traversal_matches_naive_reference() {
+        let map = TEST_INPUT.parse().unwrap();
+        for down in 0..=12 {
+            for right in 0..=25 {
+                assert_eq!(
+                    traverse_map(&map, (down, right)).unwrap(),
+                    naive_traverse(TEST_INPUT, (down, right)),
+                    "slope ({}, {})", down, right,
+                );
+            }
+        }
+
+        // Every map of up to 3x3 squares, for slopes up to twice its size
+        for width in 1..=3 {
+            for height in 1..=3 {
+                for bits in 0..1u32 << (width * height) {
+                    let input: Vec<String> = (0..height)
+                        .map(|y| (0..width)
+                            .map(|x| if bits & (1 << (y * width + x)) != 0 { '#' } else { '.' })
+                            .collect())
+                        .collect();
+                    let input = input.join("\n");
+                    let map = input.parse().unwrap();
+
+                    for down in 0..=2 * height {
+                        for right in 0..=2 * width {
+                            assert_eq!(
+                                traverse_map(&map, (down, right)).unwrap(),
+                                naive_traverse(&input, (down, right)),
+                                "slope ({}, {}) on {:?}", down, right, input,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Steps through the map literally for every topology, looking squares
+    /// up in the rows of the input.
+    fn reference_traverse(input: &str, topology: Topology, (down, right): (usize, usize)) -> Result<Vec<Slope>, &'static str> {
+        let rows: Vec<Vec<char>> = input.lines().map(|row| row.chars().collect()).collect();
+        let (width, height) = (rows.first().map_or(0, |row| row.len()), rows.len());
+        let square = |x: usize, y: usize| Slope::try_from(rows[y % height][x % width]).unwrap();
+        let mut result = vec![];
+
+        match topology {
+            Topology::Horizontal => return Ok(naive_traverse(input, (down, right))),
+            Topology::Torus => {
+                // One lap can't be longer than the number of squares
+                for step in 1..=width * height {
+                    result.push(square(step * right, step * down));
+                    if (step * right) % width == 0 && (step * down) % height == 0 {
+                        break;
+                    }
+                }
+            }
+            Topology::Bounded | Topology::BoundedStrict => {
+                for step in 1.. {
+                    let (x, y) = (step * right, step * down);
+                    if (down, right) == (0, 0) || y >= height {
+                        break;
+                    }
+                    if x >= width {
+                        if topology == Topology::BoundedStrict {
+                            return Err("Path leaves the right edge of the map");
+                        }
+                        break;
+                    }
+                    result.push(square(x, y));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn test_traversal_fuzz() {
+        let mut rng = Rng::new(2020);
+        let topologies = [Topology::Horizontal, Topology::Torus, Topology::Bounded, Topology::BoundedStrict];
+
+        for round in 0..3000 {
+            let (width, height) = (1 + rng.below(40), 1 + rng.below(40));
+            let density = rng.next_f64();
+            let mut map = random_map(&mut rng, width, height, density).unwrap();
+            let input = map.to_string();
+            let slopes: Vec<(usize, usize)> = (0..8)
+                .map(|_| (rng.below(height + 2), rng.below(3 * width + 1)))
+                .collect();
+
+            for &topology in &topologies {
+                map.topology = topology;
+                for &slope in &slopes {
+                    assert_eq!(
+                        traverse_map(&map, slope),
+                        reference_traverse(&input, topology, slope),
+                        "round {}: slope {:?} on {:?} {:?}", round, slope, topology, input,
+                    );
+                }
+            }
+
+            map.topology = Topology::Horizontal;
+            let counts: Vec<usize> = slopes.iter()
+                .map(|&slope| naive_traverse(&input, slope).iter().filter(|v| is_a_tree(v)).count())
+                .collect();
+            assert_eq!(TreeBitmap::new(&map).count_trees(&slopes), counts, "round {}: {:?}", round, input);
+        }
+    }
+
+    #[test]
+    fn test_traversal_edge_cases() {
+        let map: Map = "#.\n.#\n#.\n.#\n#.".parse().unwrap();
+
+        // Straight down stays in the first column
+        assert_eq!(traverse_map(&map, (1, 0)).unwrap(), &[Slope::OpenSquare, Slope::Tree, Slope::OpenSquare, Slope::Tree]);
+        // Moving a whole width to the right is the same as not moving
+        assert_eq!(traverse_map(&map, (2, 2)).unwrap(), traverse_map(&map, (2, 0)).unwrap());
+        // Landing exactly on the last row counts, overshooting it doesn't
+        assert_eq!(traverse_map(&map, (4, 2)).unwrap(), &[Slope::Tree]);
+        assert_eq!(traverse_map(&map, (5, 1)).unwrap(), &[]);
+        assert_eq!(traverse_map(&map, (0, 1)).unwrap(), &[]);
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_parsing_options() {
+        assert_eq!(Options::from_args(args(&[])), Ok(Options::default()));
+        assert_eq!(
+            Options::from_args(args(&["--slope", "3,1", "-s", "1, 2"])),
+            Ok(Options { slopes: vec![(1, 3), (2, 1)], ..Options::default() }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--part1"])),
+            Ok(Options { slopes: vec![(1, 3)], ..Options::default() }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--part1", "--slope", "1,1"])),
+            Err("--part1 can't be combined with --slope"),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--search", "--max-down", "2"])),
+            Ok(Options {
+                slopes: PART_TWO_SLOPES.to_vec(),
+                search: Some(SearchBounds { max_down: Some(2), max_right: None }),
+                ..Options::default()
+            }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--search", "--part1"])),
+            Err("--search can't be combined with --slope, --part1 or --render"),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--render", "svg", "--part1"])),
+            Ok(Options { slopes: vec![(1, 3)], render: Some(RenderFormat::Svg), ..Options::default() }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["-v", "--trace", "steps.tsv"])),
+            Ok(Options {
+                verbosity: Verbosity::Info,
+                trace: Some("steps.tsv".to_string()),
+                ..Options::default()
+            }),
+        );
+        assert_eq!(Options::from_args(args(&["-vv"])).unwrap().verbosity, Verbosity::Steps);
+        for &mode in &[&["--search"][..], &["--render", "svg"], &["--route"], &["--generate", "3,3"]] {
+            for &tracing in &[&["--trace", "steps.tsv"][..], &["-vv"], &["-v", "-v"]] {
+                assert_eq!(
+                    Options::from_args(args(&[mode, tracing].concat())),
+                    Err("--trace and -vv can't be combined with --generate, --route, --search or --render"),
+                );
+            }
+        }
+        assert_eq!(Options::from_args(args(&["--search", "-v"])).unwrap().verbosity, Verbosity::Info);
+        assert_eq!(Options::from_args(args(&["-v", "-v", "-v"])).unwrap().verbosity, Verbosity::Steps);
+        assert_eq!(
+            Options::from_args(args(&["--bitmap", "--part1"])),
+            Ok(Options { slopes: vec![(1, 3)], bitmap: true, ..Options::default() }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--bitmap", "--search"])),
+            Err("--bitmap can't be combined with --search or --render"),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--bitmap", "--tile", "^=2"])),
+            Err("--bitmap only counts trees, without tiles or steps to trace"),
+        );
+        assert_eq!(Options::from_args(args(&["--render", "gif"])), Err("Unknown render format"));
+        assert_eq!(Options::from_args(args(&["--max-right", "4"])), Err("Search bounds need --search"));
+        assert_eq!(Options::from_args(args(&["--slope"])), Err("Missing value for --slope"));
+        assert_eq!(Options::from_args(args(&["--slope", "3"])), Err("Slopes are written RIGHT,DOWN"));
+        assert_eq!(Options::from_args(args(&["--slope", "3,x"])), Err("Invalid value for --slope"));
+        assert_eq!(Options::from_args(args(&["--slope", "3,0"])), Err("A slope has to move down"));
+    }
+
+    #[test]
+    fn test_slope_reports() {
+        let map = TEST_INPUT.parse().unwrap();
+        let reports: Vec<SlopeReport> = PART_TWO_SLOPES.iter()
+            .map(|&slope| SlopeReport::from_path(&traverse_map(&map, slope).unwrap(), &Legend::default()))
+            .collect();
+
+        assert_eq!(reports, vec![
+            SlopeReport { trees: 2, length: 10, cost: Some(2) },
+            SlopeReport { trees: 7, length: 10, cost: Some(7) },
+            SlopeReport { trees: 3, length: 10, cost: Some(3) },
+            SlopeReport { trees: 4, length: 10, cost: Some(4) },
+            SlopeReport { trees: 2, length: 5, cost: Some(2) },
+        ]);
+        assert_eq!(reports.iter().map(|report| report.trees).product::<usize>(), 336);
+    }
+
+    #[test]
+    fn test_terrain_costs() {
+        let mut legend = Legend::default();
+        legend.add("^=5").unwrap();
+        legend.add("~=2").unwrap();
+        let input = ".^~\n#~.\n^.#\n~^#";
+
+        assert_eq!(
+            input.parse::<Map>(),
+            Err(MapParseError::UnknownSquare { row: 1, column: 2, found: '^' }),
+        );
+        let map = Map::parse(input, &legend).unwrap();
+        assert_eq!(map.to_string(), input);
+
+        let path = traverse_map(&map, (1, 1)).unwrap();
+        assert_eq!(path, &[Slope::Terrain('~'), Slope::Tree, Slope::Terrain('~')]);
+        assert_eq!(SlopeReport::from_path(&path, &legend), SlopeReport { trees: 1, length: 3, cost: Some(5) });
+        assert_eq!(
+            SlopeReport::from_path(&traverse_map(&map, (1, 2)).unwrap(), &legend),
+            SlopeReport { trees: 0, length: 3, cost: Some(2) },
+        );
+
+        // Costs too large to add up
+        let mut expensive = Legend::default();
+        expensive.add(&format!("#={}", u64::MAX)).unwrap();
+        let map = Map::parse("##\n##\n##", &expensive).unwrap();
+        assert_eq!(
+            SlopeReport::from_path(&traverse_map(&map, (1, 0)).unwrap(), &expensive),
+            SlopeReport { trees: 2, length: 2, cost: None },
+        );
+        assert_eq!(
+            SlopeReport::from_path(&traverse_map(&map, (2, 0)).unwrap(), &expensive),
+            SlopeReport { trees: 1, length: 1, cost: Some(u64::MAX) },
+        );
+
+        let options = Options::from_args(args(&["--tile", "^=5", "--tile", "~=2"])).unwrap();
+        assert_eq!(options.legend, legend);
+        assert_eq!(Options::from_args(args(&["--tile", "^"])), Err("Tiles are written SYMBOL=COST"));
+    }
+
+    #[test]
+    fn test_ranking_slopes() {
+        let map = TEST_INPUT.parse().unwrap();
+        let ranking = rank_slopes(&map, &Legend::default(), 12, 25);
+
+        // Every slope is ranked once, with the same counts as walking it alone
+        assert_eq!(ranking.len(), 12 * 26);
+        for (slope, report) in &ranking {
+            assert_eq!(report, &SlopeReport::from_path(&traverse_map(&map, *slope).unwrap(), &Legend::default()), "slope {:?}", slope);
+        }
+        for pair in ranking.windows(2) {
+            assert!((pair[0].1.trees, pair[0].0) < (pair[1].1.trees, pair[1].0));
+        }
+
+        // Many slopes miss every tree, the one with the smallest steps wins
+        assert_eq!(ranking[0], ((2, 5), SlopeReport { trees: 0, length: 5, cost: Some(0) }));
+        assert_eq!(most_trees(&ranking), Some(&((1, 3), SlopeReport { trees: 7, length: 10, cost: Some(7) })));
+
+        assert_eq!(rank_slopes(&map, &Legend::default(), 0, 3), vec![]);
+        assert_eq!(most_trees(&[]), None);
+    }
+
+    #[test]
+    fn test_tracing_steps() {
+        let path = env::temp_dir().join(format!("dec03-trace-{}.tsv", process::id()));
+        let map = "..#\n#..\n.#.".parse().unwrap();
+
+        let mut log = Log::with_trace_file(Verbosity::Quiet, path.to_str().unwrap()).unwrap();
+        assert!(log.traces_steps());
+        assert_eq!(trace_map(&map, (1, 2), &mut log).unwrap(), traverse_map(&map, (1, 2)).unwrap());
+        log.finish().unwrap();
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(trace, "2,1\t2\t1\t.\n2,1\t4\t2\t#\n");
+
+        assert!(!Log::quiet().traces_steps());
+    }
+
+    #[test]
+    fn test_topologies() {
+        let mut map: Map = "..#\n#..\n.#.\n..#".parse().unwrap();
+        let horizontal = traverse_map(&map, (1, 2)).unwrap();
+
+        map.topology = Topology::Torus;
+        // A 3x4 torus takes 12 steps to get back with a step of one each way
+        assert_eq!(path_positions(&map, (1, 1)).unwrap().len(), 12);
+        assert_eq!(path_positions(&map, (1, 2)).unwrap()[..3], horizontal_positions(&[2, 4, 6])[..]);
+        assert_eq!(
+            path_positions(&map, (2, 3)).unwrap(),
+            &[(0, 2), (0, 0)],
+        );
+        assert_eq!(path_positions(&map, (0, 1)).unwrap(), &[(1, 0), (2, 0), (0, 0)]);
+        assert_eq!(traverse_map(&map, (4, 3)).unwrap(), &[Slope::OpenSquare]);
+        assert_eq!(&traverse_map(&map, (1, 2)).unwrap()[..3], &horizontal[..]);
+
+        map.topology = Topology::Bounded;
+        assert_eq!(path_positions(&map, (1, 1)).unwrap(), &[(1, 1), (2, 2)]);
+        assert_eq!(path_positions(&map, (1, 0)).unwrap(), &[(0, 1), (0, 2), (0, 3)]);
+        assert_eq!(path_positions(&map, (0, 1)).unwrap(), &[(1, 0), (2, 0)]);
+        assert_eq!(path_positions(&map, (0, 0)).unwrap(), &[]);
+        assert_eq!(path_positions(&map, (2, 1)).unwrap(), &[(1, 2)]);
+
+        map.topology = Topology::BoundedStrict;
+        assert_eq!(path_positions(&map, (2, 1)).unwrap(), &[(1, 2)]);
+        assert_eq!(traverse_map(&map, (1, 1)), Err("Path leaves the right edge of the map"));
+        assert_eq!(traverse_map(&map, (3, 5)), Err("Path leaves the right edge of the map"));
+        assert_eq!(traverse_map(&map, (4, 5)).unwrap(), &[]);
+
+        // Only slopes that stay on the map are ranked
+        let ranking = rank_slopes(&map, &Legend::default(), 3, 2);
+        let slopes: Vec<(usize, usize)> = ranking.iter().map(|(slope, _)| *slope).collect();
+        assert_eq!(slopes, &[(2, 0), (2, 2), (3, 0), (3, 1), (1, 0), (2, 1), (3, 2)]);
+
+        assert_eq!("torus".parse(), Ok(Topology::Torus));
+        assert_eq!("sphere".parse::<Topology>(), Err("Unknown topology"));
+        assert_eq!(
+            Options::from_args(args(&["--topology", "bounded-strict"])).unwrap().topology,
+            Topology::BoundedStrict,
+        );
+        assert_eq!(
+            Options::from_args(args(&["--bitmap", "--topology", "torus"])),
+            Err("--bitmap only supports the horizontal topology"),
+        );
+    }
+
+    fn horizontal_positions(xs: &[usize]) -> Vec<(usize, usize)> {
+        xs.iter().enumerate().map(|(i, &x)| (x % 3, i + 1)).collect()
+    }
+
+    #[test]
+    fn test_generator_options() {
+        assert_eq!(
+            Options::from_args(args(&["--generate", "31,323", "--seed", "42"])).unwrap().generate,
+            Some(Generator { width: 31, height: 323, density: 0.25, seed: 42 }),
+        );
+        assert_eq!(
+            Options::from_args(args(&["--generate", "5,5", "--density", "1.5"])),
+            Err("The density has to be between 0 and 1"),
+        );
+        assert_eq!(Options::from_args(args(&["--seed", "3"])), Err("--density and --seed need --generate"));
+        assert_eq!(Options::from_args(args(&["--generate", "5"])), Err("Sizes are written WIDTH,HEIGHT"));
+    }
+
+    #[test]
+    fn test_route_options() {
+        assert_eq!(Options::from_args(args(&["--route"])).unwrap().route, Some(Move::ALL.to_vec()));
+        assert_eq!(
+            Options::from_args(args(&["--route", "--move", "down", "--render", "svg"])),
+            Ok(Options {
+                route: Some(vec![Move::Down]),
+                render: Some(RenderFormat::Svg),
+                ..Options::default()
+            }),
+        );
+        assert_eq!(Options::from_args(args(&["--move", "down"])), Err("Moves need --route"));
+        assert_eq!(Options::from_args(args(&["--route", "--move", "up"])), Err("Unknown move"));
+        assert_eq!(
+            Options::from_args(args(&["--route", "--part1"])),
+            Err("--route can't be combined with --slope, --part1, --search or --bitmap"),
+        );
+    }
+
     #[test]
     fn test_counting_trees() {
         let map = TEST_INPUT.parse().unwrap();
         assert_eq!(
-            traverse_map(&map, (1, 3)),
+            traverse_map(&map, (1, 3)).unwrap(),
             &[
               Slope::OpenSquare,
               Slope::Tree,
@@ -233,7 +1298,7 @@ mod test {
         );
 
         assert_eq!(
-            traverse_map(&map, (1, 1)),
+            traverse_map(&map, (1, 1)).unwrap(),
             &[
               Slope::OpenSquare,
               Slope::OpenSquare,
@@ -249,7 +1314,7 @@ mod test {
         );
 
         assert_eq!(
-            traverse_map(&map, (1, 5)),
+            traverse_map(&map, (1, 5)).unwrap(),
             &[
               Slope::OpenSquare,
               Slope::OpenSquare,
@@ -265,7 +1330,7 @@ mod test {
         );
 
         assert_eq!(
-            traverse_map(&map, (1, 7)),
+            traverse_map(&map, (1, 7)).unwrap(),
             &[
               Slope::OpenSquare,
               Slope::OpenSquare,
@@ -280,7 +1345,7 @@ mod test {
             ],
         );
         assert_eq!(
-            traverse_map(&map, (2, 1)),
+            traverse_map(&map, (2, 1)).unwrap(),
             &[
               Slope::Tree,
               Slope::OpenSquare,
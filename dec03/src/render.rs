@@ -0,0 +1,290 @@
+use std::fmt::Write;
+
+use crate::grid::Grid;
+use crate::{is_a_tree, Map, Slope};
+
+/// How an overlay is written out.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RenderFormat {
+    /// Text with ANSI colour codes, for a terminal
+    Ansi,
+    /// A standalone SVG image
+    Svg,
+    /// A binary PPM (P6) image
+    Ppm,
+}
+
+impl std::str::FromStr for RenderFormat {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "ansi" => Ok(RenderFormat::Ansi),
+            "svg" => Ok(RenderFormat::Svg),
+            "ppm" => Ok(RenderFormat::Ppm),
+            _ => Err("Unknown render format"),
+        }
+    }
+}
+
+/// Colours given to paths in order, as ANSI foreground codes and RGB.
+const PALETTE: &[(u8, [u8; 3])] = &[
+    (31, [220, 50, 47]),
+    (34, [38, 139, 210]),
+    (35, [211, 54, 130]),
+    (33, [181, 137, 0]),
+    (36, [42, 161, 152]),
+    (32, [133, 153, 0]),
+];
+
+const OPEN_RGB: [u8; 3] = [253, 246, 227];
+const TREE_RGB: [u8; 3] = [88, 110, 117];
+const TERRAIN_RGB: [u8; 3] = [147, 161, 161];
+
+/// Side of one square in pixels, for the images.
+const SQUARE_SIZE: usize = 8;
+
+/// Most squares an overlay may have after repeating the map, so that steep
+/// slopes far to the right fail instead of exhausting memory.
+const MAX_SQUARES: usize = 1 << 20;
+
+/// A square of the repeated map, with the first path that landed on it.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Square {
+    slope: Slope,
+    path: Option<usize>,
+}
+
+impl Square {
+    fn colour(&self) -> [u8; 3] {
+        match self.slope {
+            Slope::OpenSquare => OPEN_RGB,
+            Slope::Tree => TREE_RGB,
+            Slope::Terrain(_) => TERRAIN_RGB,
+        }
+    }
+}
+
+/// Paths drawn on top of the map, repeated to the right until it is wide
+/// enough for all of them. A path landing on a tree is marked `X`, on an
+/// open square `O`, like the diagrams of the puzzle. Where paths cross, the
+/// one given first is shown.
+pub struct Overlay {
+    squares: Grid<Square>,
+}
+
+impl Overlay {
+    /// Each path is a list of `(x, y)` positions, where `x` may lie past the
+    /// right edge of the map. Fails if the map would have to be repeated to
+    /// more than `MAX_SQUARES` squares.
+    pub fn new(map: &Map, paths: &[Vec<(usize, usize)>]) -> Result<Self, &'static str> {
+        let width = map.grid.width();
+        let rightmost = paths.iter()
+            .flatten()
+            .map(|&(x, _)| x)
+            .max()
+            .unwrap_or(0);
+        let repeats = rightmost.checked_div(width).map_or(1, |repeats| repeats + 1);
+        width.checked_mul(repeats)
+            .and_then(|width| width.checked_mul(map.grid.height()))
+            .filter(|&squares| squares <= MAX_SQUARES)
+            .ok_or("Paths reach too far to the right to render")?;
+
+        let rows = map.grid.rows().map(|row| row.iter()
+            .cycle()
+            .take(width * repeats)
+            .map(|&slope| Square { slope, path: None })
+            .collect());
+        let mut squares: Vec<Vec<Square>> = rows.collect();
+
+        for (i, path) in paths.iter().enumerate() {
+            for &(x, y) in path {
+                let square = &mut squares[y][x];
+                square.path.get_or_insert(i);
+            }
+        }
+
+        Ok(Overlay {
+            squares: Grid::from_rows(squares).expect("Map rows are equally long"),
+        })
+    }
+
+    pub fn render(&self, format: RenderFormat) -> Vec<u8> {
+        match format {
+            RenderFormat::Ansi => self.ansi().into_bytes(),
+            RenderFormat::Svg => self.svg().into_bytes(),
+            RenderFormat::Ppm => self.ppm(),
+        }
+    }
+
+    fn ansi(&self) -> String {
+        let mut out = String::new();
+        for row in self.squares.rows() {
+            for square in row {
+                match square.path {
+                    Some(i) => {
+                        let mark = if is_a_tree(&square.slope) { 'X' } else { 'O' };
+                        let _ = write!(out, "\x1b[1;{}m{}\x1b[0m", PALETTE[i % PALETTE.len()].0, mark);
+                    }
+                    None => out.push(square.slope.symbol()),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn svg(&self) -> String {
+        let (width, height) = (self.squares.width() * SQUARE_SIZE, self.squares.height() * SQUARE_SIZE);
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+            width, height,
+        );
+        let _ = writeln!(out, "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>", width, height, hex(OPEN_RGB));
+
+        for ((x, y), square) in self.squares.iter() {
+            let (left, top) = (x * SQUARE_SIZE, y * SQUARE_SIZE);
+            if square.slope != Slope::OpenSquare {
+                let _ = writeln!(
+                    out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" fill=\"{3}\"/>",
+                    left, top, SQUARE_SIZE, hex(square.colour()),
+                );
+            }
+            if let Some(i) = square.path {
+                // Hits are filled, open squares only outlined
+                let colour = hex(PALETTE[i % PALETTE.len()].1);
+                let fill = if is_a_tree(&square.slope) { colour.as_str() } else { "none" };
+                let _ = writeln!(
+                    out,
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"{}\"/>",
+                    left + SQUARE_SIZE / 2, top + SQUARE_SIZE / 2, SQUARE_SIZE / 2 - 1, fill, colour,
+                );
+            }
+        }
+
+        out.push_str("</svg>\n");
+        out
+    }
+
+    fn ppm(&self) -> Vec<u8> {
+        let (width, height) = (self.squares.width() * SQUARE_SIZE, self.squares.height() * SQUARE_SIZE);
+        let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+
+        for row in self.squares.rows() {
+            for line in 0..SQUARE_SIZE {
+                for square in row {
+                    let colour = match square.path {
+                        // Hits are drawn darker than open squares
+                        Some(i) if is_a_tree(&square.slope) => PALETTE[i % PALETTE.len()].1.map(|c| c / 2),
+                        Some(i) => PALETTE[i % PALETTE.len()].1,
+                        None => square.colour(),
+                    };
+                    for column in 0..SQUARE_SIZE {
+                        // Leave a one pixel border around every square
+                        let border = line == SQUARE_SIZE - 1 || column == SQUARE_SIZE - 1;
+                        out.extend_from_slice(if border { &OPEN_RGB } else { &colour });
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::path_positions;
+
+    /// The ANSI output with the colour codes removed.
+    fn plain(text: &str) -> String {
+        let mut out = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_overlaying_paths() {
+        let map: Map = "..#\n#..\n.#.\n..#".parse().unwrap();
+        let paths = vec![path_positions(&map, (1, 2)).unwrap(), path_positions(&map, (1, 0)).unwrap()];
+        let overlay = Overlay::new(&map, &paths).unwrap();
+
+        assert_eq!(
+            plain(&String::from_utf8(overlay.render(RenderFormat::Ansi)).unwrap()),
+            "..#..#..#\n\
+             X.O#..#..\n\
+             O#..X..#.\n\
+             O.#..#O.#\n",
+        );
+        assert!(overlay.ansi().contains("\x1b[1;31mX\x1b[0m"));
+        assert!(overlay.ansi().contains("\x1b[1;34mO\x1b[0m"));
+
+        // Crossing paths show the first one
+        let overlay = Overlay::new(&map, &[path_positions(&map, (1, 0)).unwrap(), path_positions(&map, (1, 0)).unwrap()]).unwrap();
+        assert!(!overlay.ansi().contains("\x1b[1;34m"));
+    }
+
+    #[test]
+    fn test_rendering_terrain() {
+        let mut legend = crate::Legend::default();
+        legend.add("^=2").unwrap();
+        let map = Map::parse("^.\n.^", &legend).unwrap();
+        let overlay = Overlay::new(&map, &[path_positions(&map, (1, 1)).unwrap()]).unwrap();
+
+        assert_eq!(plain(&overlay.ansi()), "^.\n.O\n");
+        assert!(overlay.svg().contains(&hex(TERRAIN_RGB)));
+    }
+
+    #[test]
+    fn test_rendering_images() {
+        let map: Map = "..#\n#..\n.#.\n..#".parse().unwrap();
+        let overlay = Overlay::new(&map, &[path_positions(&map, (1, 2)).unwrap()]).unwrap();
+
+        let svg = overlay.svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"72\" height=\"32\""));
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert_eq!(svg.matches("fill=\"none\"").count(), 2);
+        assert!(svg.ends_with("</svg>\n"));
+
+        let ppm = overlay.ppm();
+        let header = "P6\n72 32\n255\n";
+        assert!(ppm.starts_with(header.as_bytes()));
+        assert_eq!(ppm.len(), header.len() + 72 * 32 * 3);
+    }
+
+    #[test]
+    fn test_overlays_too_wide() {
+        let map: Map = "..#\n#..\n.#.\n..#".parse().unwrap();
+
+        // Exactly as many squares as allowed, and one repeat more
+        let limit = MAX_SQUARES / 12 * 3 - 1;
+        assert!(Overlay::new(&map, &[vec![(0, 0), (limit, 3)]]).is_ok());
+        assert_eq!(
+            Overlay::new(&map, &[vec![(limit + 1, 3)]]).err(),
+            Some("Paths reach too far to the right to render"),
+        );
+        assert!(Overlay::new(&map, &[path_positions(&map, (1, 100_000_000)).unwrap()]).is_err());
+        assert!(Overlay::new(&map, &[vec![(usize::MAX, 1)]]).is_err());
+        assert!(Overlay::new(&map, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_parsing_render_formats() {
+        assert_eq!("svg".parse(), Ok(RenderFormat::Svg));
+        assert_eq!("png".parse::<RenderFormat>(), Err("Unknown render format"));
+    }
+}
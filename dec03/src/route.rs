@@ -0,0 +1,224 @@
+use crate::legend::Legend;
+use crate::{is_a_tree, Map, Topology};
+
+/// A step from one row of the map to the next.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Move {
+    Down,
+    DownRight,
+    DownLeft,
+}
+
+impl Move {
+    pub const ALL: [Move; 3] = [Move::Down, Move::DownRight, Move::DownLeft];
+}
+
+impl std::str::FromStr for Move {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "down" => Ok(Move::Down),
+            "down-right" => Ok(Move::DownRight),
+            "down-left" => Ok(Move::DownLeft),
+            _ => Err("Unknown move"),
+        }
+    }
+}
+
+/// A way from the top row to the bottom row, one square per row.
+#[derive(Debug, PartialEq)]
+pub struct Route {
+    /// The `(x, y)` position in every row, from the top
+    pub positions: Vec<(usize, usize)>,
+    /// Sum of the legend's costs of all squares on the route, the first
+    /// included
+    pub cost: u64,
+    pub trees: usize,
+}
+
+/// Finds the cheapest route from any square of the top row to any square of
+/// the bottom row, taking one of `moves` at a time. Moves wrap around the
+/// sides unless the map is bounded.
+///
+/// Every move goes down exactly one row, so the cheapest way to each square
+/// only depends on the row above and the rows are solved in order. Ties go
+/// to the route ending furthest left, and on the way to a square to the
+/// leftmost square of the row above. Without any route, e.g. for an empty
+/// map, there is `None`.
+pub fn cheapest_route(map: &Map, legend: &Legend, moves: &[Move]) -> Option<Route> {
+    let grid = &map.grid;
+    let (width, height) = (grid.width(), grid.height());
+    let wraps = matches!(map.topology, Topology::Horizontal | Topology::Torus);
+    if width == 0 {
+        return None;
+    }
+
+    // Where each square of a row is reached from in the row above
+    let sources = |x: usize| moves.iter().filter_map(move |step| match step {
+        Move::Down => Some(x),
+        Move::DownRight if x > 0 => Some(x - 1),
+        Move::DownRight if wraps => Some(width - 1),
+        Move::DownLeft if x + 1 < width => Some(x + 1),
+        Move::DownLeft if wraps => Some(0),
+        _ => None,
+    });
+
+    // The cheapest cost to reach every square of the current row, `None`
+    // where no route gets there, and the column each square was reached from
+    let mut costs: Vec<Option<u64>> = (0..width).map(|x| Some(legend.cost(&grid[(x, 0)]))).collect();
+    let mut came_from: Vec<Vec<usize>> = vec![];
+
+    for y in 1..height {
+        let mut next = vec![None; width];
+        let mut from = vec![0; width];
+
+        for x in 0..width {
+            let best = sources(x)
+                .filter_map(|source| costs[source].map(|cost| (cost, source)))
+                .min();
+            if let Some((cost, source)) = best {
+                next[x] = Some(cost.saturating_add(legend.cost(&grid[(x, y)])));
+                from[x] = source;
+            }
+        }
+
+        costs = next;
+        came_from.push(from);
+    }
+
+    let (cost, mut x) = costs.iter()
+        .enumerate()
+        .filter_map(|(x, cost)| cost.map(|cost| (cost, x)))
+        .min()?;
+
+    let mut positions = vec![(x, height - 1)];
+    for (y, from) in came_from.iter().enumerate().rev() {
+        x = from[x];
+        positions.push((x, y));
+    }
+    positions.reverse();
+
+    Some(Route {
+        trees: positions.iter().filter(|&&(x, y)| is_a_tree(&grid[(x, y)])).count(),
+        positions,
+        cost,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Tries every route, by counting through the choice of start and of
+    /// move for every row.
+    fn brute_force_cost(map: &Map, legend: &Legend, moves: &[Move]) -> Option<u64> {
+        let grid = &map.grid;
+        let (width, height) = (grid.width(), grid.height());
+        let wraps = matches!(map.topology, Topology::Horizontal | Topology::Torus);
+        let choices = moves.len().pow(height as u32 - 1);
+        let mut best = None;
+
+        for start in 0..width {
+            'routes: for mut choice in 0..choices {
+                let (mut x, mut cost) = (start, legend.cost(&grid[(start, 0)]));
+                for y in 1..height {
+                    let step = moves[choice % moves.len()];
+                    choice /= moves.len();
+                    x = match step {
+                        Move::Down => x,
+                        Move::DownRight if x + 1 < width => x + 1,
+                        Move::DownRight if wraps => 0,
+                        Move::DownLeft if x > 0 => x - 1,
+                        Move::DownLeft if wraps => width - 1,
+                        _ => continue 'routes,
+                    };
+                    cost += legend.cost(&grid[(x, y)]);
+                }
+                best = Some(best.map_or(cost, |best: u64| best.min(cost)));
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn test_cheapest_route() {
+        let mut map: Map = "#..#\n\
+                            ##.#\n\
+                            .###\n\
+                            #.##".parse().unwrap();
+        let legend = Legend::default();
+
+        assert_eq!(cheapest_route(&map, &legend, &Move::ALL), Some(Route {
+            positions: vec![(1, 0), (0, 1), (0, 2), (1, 3)],
+            cost: 1,
+            trees: 1,
+        }));
+        assert_eq!(cheapest_route(&map, &legend, &[Move::Down]).unwrap().cost, 2);
+        assert_eq!(cheapest_route(&map, &legend, &[]), None);
+
+        // Only wrapping around the side avoids every tree
+        map = "##.\n.##".parse().unwrap();
+        assert_eq!(cheapest_route(&map, &legend, &[Move::DownRight]).unwrap().positions, &[(2, 0), (0, 1)]);
+        map.topology = Topology::Bounded;
+        assert_eq!(cheapest_route(&map, &legend, &[Move::DownRight]).unwrap().cost, 2);
+        assert_eq!(cheapest_route(&map, &legend, &Move::ALL).unwrap().positions, &[(0, 0), (0, 1)]);
+
+        // Other terrain counts with its cost
+        let mut legend = Legend::default();
+        legend.add("^=5").unwrap();
+        legend.add("#=2").unwrap();
+        let map = Map::parse("^.^\n.#^\n^^#", &legend).unwrap();
+        assert_eq!(cheapest_route(&map, &legend, &Move::ALL), Some(Route {
+            positions: vec![(1, 0), (0, 1), (2, 2)],
+            cost: 2,
+            trees: 1,
+        }));
+
+        assert_eq!(cheapest_route(&"".parse().unwrap(), &legend, &Move::ALL), None);
+        assert_eq!("down-left".parse(), Ok(Move::DownLeft));
+        assert_eq!("up".parse::<Move>(), Err("Unknown move"));
+    }
+
+    #[test]
+    fn test_route_matches_brute_force() {
+        let legend = Legend::default();
+        let move_sets: Vec<Vec<Move>> = (1..8u32)
+            .map(|bits| Move::ALL.iter().enumerate().filter(|(i, _)| bits & (1 << i) != 0).map(|(_, &m)| m).collect())
+            .collect();
+
+        // Every map of up to 4x3 squares, in every topology
+        for width in 1..=4 {
+            for height in 1..=3 {
+                for bits in 0..1u32 << (width * height) {
+                    let input: Vec<String> = (0..height)
+                        .map(|y| (0..width)
+                            .map(|x| if bits & (1 << (y * width + x)) != 0 { '#' } else { '.' })
+                            .collect())
+                        .collect();
+                    let mut map: Map = input.join("\n").parse().unwrap();
+
+                    for &topology in &[Topology::Horizontal, Topology::Torus, Topology::Bounded, Topology::BoundedStrict] {
+                        map.topology = topology;
+                        for moves in &move_sets {
+                            let route = cheapest_route(&map, &legend, moves);
+                            assert_eq!(
+                                route.as_ref().map(|route| route.cost),
+                                brute_force_cost(&map, &legend, moves),
+                                "{:?} {:?} on {:?}", topology, moves, input,
+                            );
+
+                            // The route is one the moves allow and costs what it says
+                            if let Some(route) = route {
+                                assert_eq!(route.positions.len(), height);
+                                let cost: u64 = route.positions.iter().map(|&p| legend.cost(&map.grid[p])).sum();
+                                assert_eq!(cost, route.cost);
+                                assert_eq!(cost as usize, route.trees);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
 use std::io::{self, BufRead};
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
 
 fn main() {
     let stdin = io::stdin();
@@ -15,165 +18,227 @@ fn main() {
 #[derive(Debug)]
 struct Passport(BTreeMap<String, String>);
 
-fn four_digit_year_validator(input: Option<&String>) -> Result<u32, &'static str> {
-    if let Some(value) = input {
-        if value.len() != 4 {
-            return Err("Invalid length");
-        }
-
-        value.parse::<u32>().map_err(|_| "Invalid integer")
-    } else {
-        Err("Invalid value")
-    }
-}
-
-type ValidationResult = Result<(), &'static str>;
 impl Passport {
     fn add(&mut self, key: String, value: String) {
         self.0.insert(key, value);
     }
+}
 
-    fn validate_birthyear(&self) -> ValidationResult {
-        self.validate_year_within_range(&"byr".to_string(), 1920..=2002)
-    }
+/// A passport whose fields have all been checked and parsed.
+#[derive(Debug, PartialEq, Clone)]
+struct ValidatedPassport {
+    birth_year: Year,
+    issue_year: Year,
+    expiration_year: Year,
+    height: Height,
+    hair_color: HexColor,
+    eye_color: EyeColor,
+    passport_id: PassportId,
+    country_id: Option<CountryId>,
+}
+
+/// Why one field of a passport is invalid. Missing fields are reported
+/// with the reason "Missing field".
+#[derive(Debug, PartialEq)]
+struct FieldError {
+    field: String,
+    reason: &'static str,
+}
 
-    fn validate_expiration_year(&self) -> ValidationResult {
-        self.validate_year_within_range(&"eyr".to_string(), 2020..=2030)
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
     }
+}
+
+const BIRTH_YEARS: RangeInclusive<u16> = 1920..=2002;
+const ISSUE_YEARS: RangeInclusive<u16> = 2010..=2020;
+const EXPIRATION_YEARS: RangeInclusive<u16> = 2020..=2030;
+
+const FIELDS: [&str; 8] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Year(u16);
 
-    fn validate_year_within_range(
-        &self,
-        key: &String,
-        range: std::ops::RangeInclusive<u32>
-    ) -> ValidationResult {
-        let year = four_digit_year_validator(self.0.get(key))?;
+impl Year {
+    /// Reads a four digit year within `range`.
+    fn within(input: &str, range: RangeInclusive<u16>) -> Result<Self, &'static str> {
+        if input.len() != 4 {
+            return Err("Invalid length");
+        }
+
+        let year = input.parse().map_err(|_| "Invalid integer")?;
         if range.contains(&year) {
-            Ok(())
+            Ok(Year(year))
         } else {
             Err("Not within range")
         }
     }
+}
 
-    fn validate_issued_year(&self) -> Result<(), &'static str> {
-        self.validate_year_within_range(&"iyr".to_string(), 2010..=2020)
-            .map_err(|err| {
-                if err == "Not within range" {
-                    "Passport is expired"
-                } else {
-                    err
-                }
-            })
-    }
-
-    fn get(&self, key: &str) -> Result<String, &'static str> {
-        match self.0.get(&key.to_string()) {
-            Some(value) => Ok(value.to_string()),
-            None => Err("Key not found"),
-        }
-    }
-
-    fn validate_eye_color(&self) -> ValidationResult {
-        let eye_color = self.get(&"ecl").map_err(|_| "Eye color not found")?;
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Height {
+    Cm(u8),
+    In(u8),
+}
 
-        let valid_eye_colors = ["amb", "blu", "brn", "gry", "grn", "hzl", "oth"];
+impl std::str::FromStr for Height {
+    type Err = &'static str;
 
-        if valid_eye_colors.contains(&eye_color.as_str()) {
-            Ok(())
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (value, range, unit): (_, _, fn(u8) -> Height) = if let Some(value) = input.strip_suffix("cm") {
+            (value, 150..=193, Height::Cm)
+        } else if let Some(value) = input.strip_suffix("in") {
+            (value, 59..=76, Height::In)
         } else {
-            Err("Invalid eye color")
-        }
-    }
+            return Err("Invalid format");
+        };
 
-    fn validate_height(&self) -> ValidationResult {
-        let height = self.get(&"hgt").map_err(|_| "Height not found")?;
-        if height.ends_with("cm") {
-            let height = height.strip_suffix("cm")
-                .unwrap()
-                .parse::<u8>()
-                .map_err(|_| "Invalid input")?;
-            if (150..=193).contains(&height) {
-                Ok(())
-            } else {
-                Err("Invalid length")
-            }
-        } else if height.ends_with("in") {
-            let height = height.strip_suffix("in")
-                .unwrap()
-                .parse::<u8>()
-                .map_err(|_| "Invalid input")?;
-            if (59..=76).contains(&height) {
-                Ok(())
-            } else {
-                Err("Invalid length")
-            }
+        let value = value.parse().map_err(|_| "Invalid input")?;
+        if range.contains(&value) {
+            Ok(unit(value))
         } else {
-            Err("Invalid format")
+            Err("Invalid length")
         }
     }
+}
 
-    fn validate_hair_color(&self) -> ValidationResult {
-        let hair_color = self.get(&"hcl").map_err(|_| "Hair color not found")?;
-        if !hair_color.starts_with("#") || hair_color.len() != 7 {
+/// An RGB colour written as `#rrggbb`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct HexColor([u8; 3]);
+
+impl std::str::FromStr for HexColor {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let digits = input.strip_prefix('#').ok_or("Invalid hex color")?;
+        if digits.len() != 6 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
             return Err("Invalid hex color");
         }
 
-        let chars = hair_color[1..=6].as_bytes();
-        for chunk in chars.chunks(2) {
-            let potential_hex = std::str::from_utf8(chunk)
-                .map_err(|_| "Invalid hex color")?;
-            u8::from_str_radix(potential_hex, 16)
-                .map_err(|_| "Invalid hex color")?;
+        let mut rgb = [0; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            *channel = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).map_err(|_| "Invalid hex color")?;
         }
-
-        Ok(())
+        Ok(HexColor(rgb))
     }
+}
 
-    fn validate_passport_number(&self) -> ValidationResult {
-        let pid = self.get(&"pid")?;
-        if pid.len() != 9 {
-            return Err("Invalid passport number");
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum EyeColor {
+    Amber,
+    Blue,
+    Brown,
+    Gray,
+    Green,
+    Hazel,
+    Other,
+}
+
+impl std::str::FromStr for EyeColor {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "amb" => Ok(EyeColor::Amber),
+            "blu" => Ok(EyeColor::Blue),
+            "brn" => Ok(EyeColor::Brown),
+            "gry" => Ok(EyeColor::Gray),
+            "grn" => Ok(EyeColor::Green),
+            "hzl" => Ok(EyeColor::Hazel),
+            "oth" => Ok(EyeColor::Other),
+            _ => Err("Invalid eye color"),
         }
-        let trimed_passport = pid.trim_start_matches('0');
-        let parsed_pid = trimed_passport.parse::<u64>().map_err(|_| "Invalid passport number")?;
+    }
+}
 
-        if trimed_passport.len() != parsed_pid.to_string().len() {
+/// A nine digit passport number, leading zeros included.
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct PassportId(u32);
+
+impl std::str::FromStr for PassportId {
+    type Err = &'static str;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input.len() != 9 || !input.bytes().all(|b| b.is_ascii_digit()) {
             return Err("Invalid passport number");
         }
 
-        Ok(())
+        input.parse().map(PassportId).map_err(|_| "Invalid passport number")
     }
+}
 
-    fn validate_fields<'a>(&self) -> Result<(), &'a str> {
-        self.validate_birthyear()?;
-        self.validate_issued_year()?;
-        self.validate_expiration_year()?;
-        self.validate_height()?;
-        self.validate_hair_color()?;
-        self.validate_eye_color()?;
-        self.validate_passport_number()?;
-
-        Ok(())
+impl fmt::Display for PassportId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:09}", self.0)
     }
+}
 
-    fn valid(&self) -> bool {
-        let num_fields = self.0.len();
+/// The country a passport is from. Anything goes, and it may be missing.
+#[derive(Debug, PartialEq, Clone)]
+struct CountryId(String);
+
+/// Parses one field of a passport, noting why if it can't.
+fn parse_field<T>(
+    fields: &BTreeMap<String, String>,
+    key: &'static str,
+    errors: &mut Vec<FieldError>,
+    parse: impl FnOnce(&str) -> Result<T, &'static str>,
+) -> Option<T> {
+    let result = match fields.get(key) {
+        Some(value) => parse(value),
+        None => Err("Missing field"),
+    };
+
+    result.map_err(|reason| errors.push(FieldError { field: key.to_string(), reason })).ok()
+}
 
-        match self.validate_fields() {
-            Ok(_) => {},
-            Err(_) => return false,
-        };
+impl TryFrom<Passport> for ValidatedPassport {
+    type Error = Vec<FieldError>;
 
-        // Verify that we don't have a extra field
-        if num_fields == 7 {
-            return true;
-        }
+    /// Checks every field, so that all errors are reported rather than only
+    /// the first one. Fields other than the eight known ones are errors too.
+    fn try_from(passport: Passport) -> Result<Self, Self::Error> {
+        let fields = passport.0;
+        let mut errors = vec![];
 
-        // Verify that the optional key is "cid"
-        if self.0.contains_key(&"cid".to_string()) {
-            return true;
+        let birth_year = parse_field(&fields, "byr", &mut errors, |value| Year::within(value, BIRTH_YEARS));
+        let issue_year = parse_field(&fields, "iyr", &mut errors, |value| {
+            Year::within(value, ISSUE_YEARS).map_err(|err| {
+                if err == "Not within range" { "Passport is expired" } else { err }
+            })
+        });
+        let expiration_year = parse_field(&fields, "eyr", &mut errors, |value| Year::within(value, EXPIRATION_YEARS));
+        let height = parse_field(&fields, "hgt", &mut errors, str::parse);
+        let hair_color = parse_field(&fields, "hcl", &mut errors, str::parse);
+        let eye_color = parse_field(&fields, "ecl", &mut errors, str::parse);
+        let passport_id = parse_field(&fields, "pid", &mut errors, str::parse);
+
+        for key in fields.keys().filter(|key| !FIELDS.contains(&key.as_str())) {
+            errors.push(FieldError { field: key.clone(), reason: "Unknown field" });
         }
 
-        false
+        match (birth_year, issue_year, expiration_year, height, hair_color, eye_color, passport_id) {
+            (
+                Some(birth_year),
+                Some(issue_year),
+                Some(expiration_year),
+                Some(height),
+                Some(hair_color),
+                Some(eye_color),
+                Some(passport_id),
+            ) if errors.is_empty() => Ok(ValidatedPassport {
+                birth_year,
+                issue_year,
+                expiration_year,
+                height,
+                hair_color,
+                eye_color,
+                passport_id,
+                country_id: fields.get("cid").map(|cid| CountryId(cid.clone())),
+            }),
+            _ => Err(errors),
+        }
     }
 }
 
@@ -184,10 +249,11 @@ impl PassportCollection {
         PassportCollection(passports)
     }
 
-    fn valid(self) -> impl Iterator<Item = Passport> {
-        self.0.into_iter().filter(|p| p.valid())
+    fn valid(self) -> impl Iterator<Item = ValidatedPassport> {
+        self.0.into_iter().filter_map(|p| ValidatedPassport::try_from(p).ok())
     }
 
+    #[cfg(test)]
     fn len(&self) -> usize {
         self.0.len()
     }
@@ -199,24 +265,23 @@ fn tokenize_passports<T: BufRead>(reader: T) -> PassportCollection {
 
     for line in reader.lines() {
         let value = line.unwrap();
-        if &value == &"" {
+        if value.is_empty() {
             passports.push(passport_buffer);
             passport_buffer = vec![];
         } else {
-            passport_buffer.push(value.to_string());
+            passport_buffer.push(value);
         }
     }
 
-    if passport_buffer.len() > 0 {
+    if !passport_buffer.is_empty() {
         passports.push(passport_buffer);
     }
 
     PassportCollection::new(passports.iter().map(|p| {
         let mut passport = Passport(BTreeMap::new());
         for parts in p {
-            parts.split(" ").for_each(|v| {
-                let key_pair = v.split(":")
-                    .map(|v| v)
+            parts.split(' ').for_each(|v| {
+                let key_pair = v.split(':')
                     .collect::<Vec<&str>>();
 
                 let key = key_pair[0];
@@ -266,93 +331,105 @@ mod test {
 
     #[test]
     fn test_validate_birthyear() {
-        let mut passport = Passport(BTreeMap::new());
-        passport.add("byr".to_string(), "1919".to_string());
-        assert_eq!(passport.validate_birthyear(), Err("Not within range"));
-
-        passport.add("byr".to_string(), "1920".to_string());
-        assert_eq!(passport.validate_birthyear(), Ok(()));
-
-        passport.add("byr".to_string(), "2002".to_string());
-        assert_eq!(passport.validate_birthyear(), Ok(()));
-
-        passport.add("byr".to_string(), "11920".to_string());
-        assert_eq!(passport.validate_birthyear(), Err("Invalid length"));
+        assert_eq!(Year::within("1919", BIRTH_YEARS), Err("Not within range"));
+        assert_eq!(Year::within("1920", BIRTH_YEARS), Ok(Year(1920)));
+        assert_eq!(Year::within("2002", BIRTH_YEARS), Ok(Year(2002)));
+        assert_eq!(Year::within("11920", BIRTH_YEARS), Err("Invalid length"));
+        assert_eq!(Year::within("19x0", BIRTH_YEARS), Err("Invalid integer"));
     }
 
     #[test]
     fn test_validate_issue_year() {
-        let mut passport = Passport(BTreeMap::new());
-        passport.add("iyr".to_string(), "1919".to_string());
-        assert_eq!(passport.validate_issued_year(), Err("Passport is expired"));
-
-        passport.add("iyr".to_string(), "2031".to_string());
-        assert_eq!(passport.validate_issued_year(), Err("Passport is expired"));
-
-        passport.add("iyr".to_string(), "2010".to_string());
-        assert_eq!(passport.validate_issued_year(), Ok(()));
-
-        passport.add("iyr".to_string(), "11920".to_string());
-        assert_eq!(passport.validate_issued_year(), Err("Invalid length"));
+        assert_eq!(Year::within("1919", ISSUE_YEARS), Err("Not within range"));
+        assert_eq!(Year::within("2031", ISSUE_YEARS), Err("Not within range"));
+        assert_eq!(Year::within("2010", ISSUE_YEARS), Ok(Year(2010)));
+        assert_eq!(Year::within("11920", ISSUE_YEARS), Err("Invalid length"));
     }
 
     #[test]
     fn test_validate_height() {
-        let mut passport = Passport(BTreeMap::new());
-        passport.add("hgt".to_string(), "170cm".to_string());
-        assert_eq!(passport.validate_height(), Ok(()));
-
-        passport.add("hgt".to_string(), "59cm".to_string());
-        assert_eq!(passport.validate_height(), Err("Invalid length"));
-
-        passport.add("hgt".to_string(), "59in".to_string());
-        assert_eq!(passport.validate_height(), Ok(()));
-
-        passport.add("hgt".to_string(), "77in".to_string());
-        assert_eq!(passport.validate_height(), Err("Invalid length"));
-
-        passport.add("hgt".to_string(), "77".to_string());
-        assert_eq!(passport.validate_height(), Err("Invalid format"));
+        assert_eq!("170cm".parse(), Ok(Height::Cm(170)));
+        assert_eq!("59cm".parse::<Height>(), Err("Invalid length"));
+        assert_eq!("59in".parse(), Ok(Height::In(59)));
+        assert_eq!("77in".parse::<Height>(), Err("Invalid length"));
+        assert_eq!("77".parse::<Height>(), Err("Invalid format"));
+        assert_eq!("xin".parse::<Height>(), Err("Invalid input"));
     }
 
     #[test]
     fn test_validate_hair_color() {
-        let mut passport = Passport(BTreeMap::new());
-        passport.add("hcl".to_string(), "#HHHHHH".to_string());
-        assert_eq!(passport.validate_hair_color(), Err("Invalid hex color"));
-
-        passport.add("hcl".to_string(), "#FFFFFF".to_string());
-        assert_eq!(passport.validate_hair_color(), Ok(()));
-
-        passport.add("hcl".to_string(), "#FFF".to_string());
-        assert_eq!(passport.validate_hair_color(), Err("Invalid hex color"));
-
-        passport.add("hcl".to_string(), "FFFFFF".to_string());
-        assert_eq!(passport.validate_hair_color(), Err("Invalid hex color"));
+        assert_eq!("#HHHHHH".parse::<HexColor>(), Err("Invalid hex color"));
+        assert_eq!("#FFFFFF".parse(), Ok(HexColor([255, 255, 255])));
+        assert_eq!("#12ab0f".parse(), Ok(HexColor([0x12, 0xab, 0x0f])));
+        assert_eq!("#FFF".parse::<HexColor>(), Err("Invalid hex color"));
+        assert_eq!("FFFFFF".parse::<HexColor>(), Err("Invalid hex color"));
+        assert_eq!("#+1+1+1".parse::<HexColor>(), Err("Invalid hex color"));
     }
 
     #[test]
     fn test_validate_eye_color() {
-        let mut passport = Passport(BTreeMap::new());
-
-        passport.add("ecl".to_string(), "wat".to_string());
-        assert_eq!(passport.validate_eye_color(), Err("Invalid eye color"));
-
-        passport.add("ecl".to_string(), "brn".to_string());
-        assert_eq!(passport.validate_eye_color(), Ok(()));
+        assert_eq!("wat".parse::<EyeColor>(), Err("Invalid eye color"));
+        assert_eq!("brn".parse(), Ok(EyeColor::Brown));
     }
 
     #[test]
     fn test_passport_number() {
-        let mut passport = Passport(BTreeMap::new());
-
-        passport.add("pid".to_string(), "000000001".to_string());
-        assert_eq!(passport.validate_passport_number(), Ok(()));
+        assert_eq!("000000001".parse(), Ok(PassportId(1)));
+        assert_eq!(PassportId(1).to_string(), "000000001");
+        assert_eq!("0123456789".parse::<PassportId>(), Err("Invalid passport number"));
+        assert_eq!("0A2345678".parse::<PassportId>(), Err("Invalid passport number"));
+        assert_eq!("+12345678".parse::<PassportId>(), Err("Invalid passport number"));
+    }
 
-        passport.add("pid".to_string(), "0123456789".to_string());
-        assert_eq!(passport.validate_passport_number(), Err("Invalid passport number"));
+    fn passport(fields: &str) -> Passport {
+        let mut passport = Passport(BTreeMap::new());
+        for field in fields.split_whitespace() {
+            let (key, value) = field.split_once(':').unwrap();
+            passport.add(key.to_string(), value.to_string());
+        }
+        passport
+    }
 
-        passport.add("pid".to_string(), "0A2345678".to_string());
-        assert_eq!(passport.validate_passport_number(), Err("Invalid passport number"));
+    #[test]
+    fn test_validated_passport() {
+        assert_eq!(
+            ValidatedPassport::try_from(passport(
+                "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980 hcl:#623a2f",
+            )),
+            Ok(ValidatedPassport {
+                birth_year: Year(1980),
+                issue_year: Year(2012),
+                expiration_year: Year(2030),
+                height: Height::In(74),
+                hair_color: HexColor([0x62, 0x3a, 0x2f]),
+                eye_color: EyeColor::Green,
+                passport_id: PassportId(87499704),
+                country_id: None,
+            }),
+        );
+
+        let validated = ValidatedPassport::try_from(passport(
+            "eyr:2029 ecl:blu cid:129 byr:1989 iyr:2014 pid:896056539 hcl:#a97842 hgt:165cm",
+        )).unwrap();
+        assert_eq!(validated.country_id, Some(CountryId("129".to_string())));
+
+        // Every problem is reported, in field order
+        let errors = ValidatedPassport::try_from(passport(
+            "eyr:1972 cid:100 hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 foo:bar",
+        )).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<String>>(),
+            vec![
+                "byr: Missing field",
+                "eyr: Not within range",
+                "hgt: Invalid format",
+                "pid: Invalid passport number",
+                "foo: Unknown field",
+            ],
+        );
+
+        let errors = ValidatedPassport::try_from(passport("iyr:2021")).unwrap_err();
+        assert_eq!(errors.len(), 7);
+        assert_eq!(errors[1], FieldError { field: "iyr".to_string(), reason: "Passport is expired" });
     }
 }